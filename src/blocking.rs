@@ -1,50 +1,89 @@
-use super::{Page, SimpleCrawler};
-use anyhow::{Context, Result};
+use super::checkpoint::Checkpoint;
+use super::fetch::{self, Attempt};
+use super::limits::Budget;
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::warc::WarcWriter;
+use super::{body, handler, CrawlError, SimpleCrawler};
+use std::sync::Arc;
 
-impl SimpleCrawler {
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// This is only available if the blocking feature has been enabled in this library. I
+    /// recommend this isn't used and the `crawl` function be used instead as it's asynchronous
+    /// and performs better.
+    /// This can be used with `let simple_crawler = SimpleCrawler::new().url(&str).crawl_blocking()`
+    /// Urls which can't be fetched are marked as failed and the crawl carries on.
     #[cfg(feature = "blocking")]
-    fn request_body_blocking(self) -> Result<Self> {
-        let mut new = self;
+    pub fn crawl_blocking(self) -> Result<Self, CrawlError> {
+        let client = fetch::client_blocking(&self.config)?;
+        let budget = Budget::new(&self.config);
+        let mut checkpoint = Checkpoint::from_config(&self.config)?;
+        let hosts = HostLimiter::new(&self);
+        let mut new = self
+            .load_robots_blocking(&client, &hosts)
+            .load_sitemaps_blocking(&client, &hosts)?;
+        let mut warc = WarcWriter::from_config(&new.config);
+        new.checkpoint_all(&mut checkpoint)?;
 
-        for (url, page) in new.urls.iter_mut() {
-            if !page.crawled {
-                let body = reqwest::blocking::get(url)
-                    .with_context(|| format!("Request blocking failed GET request for {}", url))?
-                    .text()
-                    .with_context(|| {
-                        format!("Request blocking failed to extract text for {}", url)
-                    })?;
-                *page = Page::with_values(&body, &true);
+        while !new.stop_at_limit(&budget)? {
+            let mut attempt = match new.next_pending_blocking(&client, &hosts)? {
+                Some(url) => match new.urls.get(&url)? {
+                    Some(page) => Attempt::new(&url, page),
+                    None => continue,
+                },
+                None => break,
+            };
+            // A redirect is only followed once its hop has been checked.
+            loop {
+                let base_url = &new.base_url;
+                attempt = fetch::fetch_page_blocking(
+                    &client,
+                    attempt,
+                    base_url,
+                    &new.config,
+                    &budget,
+                    &hosts,
+                );
+                let hop = match attempt.redirect() {
+                    Some(hop) => hop.to_owned(),
+                    None => break,
+                };
+                if !new.follow_redirect_blocking(&client, &hosts, &attempt.url, &hop)? {
+                    attempt.stop_redirect(&new.config);
+                }
             }
+            let (url, mut page, exchanges) = (attempt.url, attempt.page, attempt.exchanges);
+            let handled = handler::handle_page_blocking(&url, &mut page, &new.config);
+            if let Some(warc) = warc.as_mut() {
+                warc.write(&url, &page, &exchanges)?;
+            }
+            page.stored_body = body::retain(&new.config.body_retention, &page)?;
+            let body = std::mem::take(&mut page.body);
+            new.record_fetched(&url, page, &budget)?;
+            new.add_redirect_target(&url)?;
+            new.get_urls(&url, &body)?;
+            new.add_handled_urls(&url, &handled)?;
+            new.checkpoint_changed(&mut checkpoint)?;
         }
-
+        new.stop_at_limit(&budget)?;
+        new.checkpoint_finished(checkpoint)?;
         Ok(new)
     }
 
-    /// This is only available if the blocking feature has been enabled in this library. I
-    /// recommend this isn't used and the `crawl` function be used instead as it's asynchronous
-    /// and performs better.
-    /// This can be used with `let simple_crawler = SimpleCrawler::new().url(&str).crawl_blocking()`
+    /// Takes the next url to request from the store's queue like `next_pending` with a blocking
+    /// client.
     #[cfg(feature = "blocking")]
-    pub fn crawl_blocking(self) -> Result<Self> {
-        let mut new = self;
-
-        // TODO need to better manage stack usage at this point.
-        while new
-            .to_owned()
-            .urls
-            .iter()
-            .filter(|(_, page)| page.crawled == false)
-            .count()
-            > 0
-        {
-            new = new
-                .to_owned()
-                .request_body_blocking()
-                .with_context(|| format!("Crawl blocking failed with request"))?
-                .get_urls()
-                .with_context(|| format!("Crawl blocking failed with getting urls"))?;
+    fn next_pending_blocking(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        hosts: &Arc<HostLimiter>,
+    ) -> Result<Option<String>, CrawlError> {
+        while let Some(url) = self.urls.next_pending()? {
+            self.load_robots_for_blocking(client, &url, hosts);
+            if !self.skip_if_disallowed(&url)? {
+                return Ok(Some(url));
+            }
         }
-        Ok(new)
+        Ok(None)
     }
 }
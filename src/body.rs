@@ -0,0 +1,163 @@
+use super::{CrawlError, Page, PageState};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What's done with each fetched page's body once its links have been found. The body kept is the
+/// text the page was decoded to using its charset, written as UTF-8, rather than the bytes which
+/// were received. Those are in the WARC records if the crawl is archived.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum BodyRetention {
+    /// Drop the body. This is the default.
+    #[default]
+    Discard,
+    /// Keep bodies of up to this many bytes on the page. Larger bodies are dropped.
+    Memory(u64),
+    /// Write bodies gzipped to this directory named after the SHA-256 of the body's UTF-8 text,
+    /// so a body served at more than one url is only written once.
+    Disk(PathBuf),
+}
+
+/// A fetched page's body kept by the crawl's `BodyRetention` as decoded text.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum StoredBody {
+    /// The body held on the page.
+    Memory(String),
+    /// The SHA-256 of the body's UTF-8 text as lower case hex and the gzipped file it's in.
+    Disk { digest: String, path: PathBuf },
+}
+
+impl StoredBody {
+    /// The body. Bodies on disk are read back and decompressed.
+    pub fn read(&self) -> Result<String, CrawlError> {
+        match self {
+            StoredBody::Memory(body) => Ok(body.to_owned()),
+            StoredBody::Disk { path, .. } => {
+                let error = |e| body_error(path, e);
+                let mut body = String::new();
+                GzDecoder::new(File::open(path).map_err(error)?)
+                    .read_to_string(&mut body)
+                    .map_err(error)?;
+                Ok(body)
+            }
+        }
+    }
+
+    /// The SHA-256 of a body kept on disk, taken of its decoded text.
+    pub fn digest(&self) -> Option<&str> {
+        match self {
+            StoredBody::Memory(_) => None,
+            StoredBody::Disk { digest, .. } => Some(digest),
+        }
+    }
+
+    /// The file a body kept on disk is in.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            StoredBody::Memory(_) => None,
+            StoredBody::Disk { path, .. } => Some(path),
+        }
+    }
+}
+
+/// Keeps a page's body the way the crawl was asked to. Only fetched pages have their body kept.
+pub(crate) fn retain(
+    retention: &BodyRetention,
+    page: &Page,
+) -> Result<Option<StoredBody>, CrawlError> {
+    if page.state != PageState::Fetched {
+        return Ok(None);
+    }
+    match retention {
+        BodyRetention::Discard => Ok(None),
+        BodyRetention::Memory(max) if page.body.len() as u64 > *max => Ok(None),
+        BodyRetention::Memory(_) => Ok(Some(StoredBody::Memory(page.body.to_owned()))),
+        BodyRetention::Disk(dir) => write(dir, &page.body).map(Some),
+    }
+}
+
+// Bodies are spread over directories named after the first two characters of their digest so no
+// one directory gets too big.
+fn write(dir: &Path, body: &str) -> Result<StoredBody, CrawlError> {
+    let digest = Sha256::digest(body.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let subdir = dir.join(&digest[..2]);
+    let path = subdir.join(format!("{}.gz", digest));
+    if !path.exists() {
+        let error = |e| body_error(&path, e);
+        fs::create_dir_all(&subdir).map_err(error)?;
+        // Written to a temporary file first so a body is never seen half written.
+        let tmp = path.with_extension(format!("{:x}.tmp", rand::random::<u64>()));
+        let mut encoder =
+            GzEncoder::new(File::create(&tmp).map_err(error)?, Compression::default());
+        encoder
+            .write_all(body.as_bytes())
+            .and_then(|_| encoder.finish())
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(error)?;
+    }
+    Ok(StoredBody::Disk { digest, path })
+}
+
+fn body_error(path: &Path, e: std::io::Error) -> CrawlError {
+    CrawlError::BodyStore {
+        path: path.to_owned(),
+        source: Arc::new(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retain, BodyRetention, StoredBody};
+    use crate::{CrawlError, Page, PageState};
+
+    #[test]
+    fn retain_test() -> Result<(), CrawlError> {
+        let dir = std::env::temp_dir().join(format!("retain_test_{}", std::process::id()));
+        let page = |body: &str| Page {
+            body: body.to_owned(),
+            state: PageState::Fetched,
+            ..Page::new()
+        };
+
+        assert_eq!(retain(&BodyRetention::Discard, &page("body"))?, None);
+        assert_eq!(
+            retain(&BodyRetention::Memory(4), &page("body"))?,
+            Some(StoredBody::Memory("body".to_owned()))
+        );
+        assert_eq!(retain(&BodyRetention::Memory(3), &page("body"))?, None);
+        assert_eq!(retain(&BodyRetention::Memory(4), &Page::new())?, None);
+
+        let disk = BodyRetention::Disk(dir.to_owned());
+        let stored = retain(&disk, &page("body"))?.unwrap();
+        assert_eq!(
+            stored.digest(),
+            Some("230d8358dc8e8890b4c58deeb62912ee2f20357ae92a5cc861b98e68fe31acb5")
+        );
+        assert!(stored.path().unwrap().starts_with(dir.join("23")));
+        assert_eq!(stored.read()?, "body");
+        // The same body is only written once.
+        assert_eq!(retain(&disk, &page("body"))?.as_ref(), Some(&stored));
+        assert_eq!(std::fs::read_dir(dir.join("23")).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use reqwest::Url;
+
+/// Query parameters which only track where a visitor came from.
+pub const TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "_ga", "igshid",
+];
+
+/// Which query parameters are kept when a url is canonicalized.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum QueryPolicy {
+    /// Keep every parameter.
+    KeepAll,
+    /// Remove the query.
+    DropAll,
+    /// Keep only parameters with these names, for example `page`.
+    Allow(Vec<String>),
+    /// Remove the parameters in `TRACKING_PARAMS` and keep the rest.
+    DropTracking,
+}
+
+impl QueryPolicy {
+    fn keeps(&self, name: &str) -> bool {
+        match self {
+            QueryPolicy::KeepAll => true,
+            QueryPolicy::DropAll => false,
+            QueryPolicy::Allow(names) => names.iter().any(|n| n == name),
+            QueryPolicy::DropTracking => {
+                !TRACKING_PARAMS.iter().any(|t| match t.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => name == *t,
+                })
+            }
+        }
+    }
+}
+
+/// The order of a url's query parameters when it's canonicalized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum QueryOrder {
+    /// Leave the parameters in the order they were found.
+    Keep,
+    /// Sort the parameters by name so `?b=1&a=2` and `?a=2&b=1` are the same page.
+    Sort,
+}
+
+/// What's done with a trailing slash on a url's path when it's canonicalized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum TrailingSlash {
+    /// Leave the path as it was found.
+    Keep,
+    /// Add a slash to paths whose last segment has no file extension, so `/docs` becomes
+    /// `/docs/` but `/page.html` is left alone.
+    Add,
+    /// Remove the slash from every path except the root.
+    Remove,
+}
+
+/// The Canonicalizer struct sets how urls are rewritten before they're used as keys in
+/// `SimpleCrawler::urls`, so different spellings of one url are only crawled once. The scheme
+/// and host are always lower cased, default ports dropped, dot segments removed and fragments
+/// stripped. For example
+/// `let simple_crawler = SimpleCrawler::builder().canonicalizer(Canonicalizer::new().query(QueryOrder::Sort)).build()`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Canonicalizer {
+    percent_encoding: bool,
+    query_params: QueryPolicy,
+    query: QueryOrder,
+    trailing_slash: TrailingSlash,
+    rel_canonical: bool,
+}
+
+impl Default for Canonicalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Canonicalizer {
+    /// Percent-encoding is normalised, tracking parameters are dropped and
+    /// `<link rel="canonical">` is honoured but the order of the query and trailing slash are kept
+    /// as they were found.
+    pub fn new() -> Self {
+        Canonicalizer {
+            percent_encoding: true,
+            query_params: QueryPolicy::DropTracking,
+            query: QueryOrder::Keep,
+            trailing_slash: TrailingSlash::Keep,
+            rel_canonical: true,
+        }
+    }
+
+    /// Whether percent-encoded unreserved characters are decoded and other escapes upper cased,
+    /// so `/%7euser` and `/~user` are the same page.
+    pub fn percent_encoding(self, percent_encoding: bool) -> Self {
+        let mut new = self;
+        new.percent_encoding = percent_encoding;
+        new
+    }
+
+    /// Sets which query parameters are kept.
+    pub fn query_params(self, query_params: QueryPolicy) -> Self {
+        let mut new = self;
+        new.query_params = query_params;
+        new
+    }
+
+    /// Sets the order of the query parameters.
+    pub fn query(self, query: QueryOrder) -> Self {
+        let mut new = self;
+        new.query = query;
+        new
+    }
+
+    /// Sets what's done with a trailing slash.
+    pub fn trailing_slash(self, trailing_slash: TrailingSlash) -> Self {
+        let mut new = self;
+        new.trailing_slash = trailing_slash;
+        new
+    }
+
+    /// Whether a page's `<link rel="canonical">` is followed instead of the page's own url. The
+    /// page is recorded as an alias of its canonical url and later links to it are crawled as
+    /// the canonical url.
+    pub fn rel_canonical(self, rel_canonical: bool) -> Self {
+        let mut new = self;
+        new.rel_canonical = rel_canonical;
+        new
+    }
+
+    pub(crate) fn follows_rel_canonical(&self) -> bool {
+        self.rel_canonical
+    }
+
+    /// Rewrites a url which has already been parsed, and so already has its scheme and host
+    /// lower cased, default port dropped and dot segments removed.
+    pub(crate) fn canonicalize(&self, url: &Url) -> Url {
+        let mut new = url.to_owned();
+        // Fragments are only used by the browser so every fragment is the same page.
+        new.set_fragment(None);
+
+        if self.percent_encoding {
+            let path = normalise_percent_encoding(new.path());
+            new.set_path(&path);
+            if let Some(query) = new.query().map(normalise_percent_encoding) {
+                new.set_query(Some(&query));
+            }
+        }
+
+        match self.trailing_slash {
+            TrailingSlash::Keep => {}
+            TrailingSlash::Add => {
+                let last = new.path().rsplit('/').next().unwrap_or_default();
+                if !last.is_empty() && !last.contains('.') {
+                    let path = format!("{}/", new.path());
+                    new.set_path(&path);
+                }
+            }
+            TrailingSlash::Remove => {
+                if new.path() != "/" && new.path().ends_with('/') {
+                    let path = new.path().trim_end_matches('/').to_owned();
+                    new.set_path(if path.is_empty() { "/" } else { &path });
+                }
+            }
+        }
+
+        let name = |param: &str| param.split('=').next().unwrap_or_default().to_owned();
+        let mut params = new
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|param| !param.is_empty() && self.query_params.keeps(&name(param)))
+            .map(|param| param.to_owned())
+            .collect::<Vec<_>>();
+        if self.query == QueryOrder::Sort {
+            // Sorted by name only so repeated parameters keep their order.
+            params.sort_by_key(|param| name(param));
+        }
+        // An empty query is the same page as no query.
+        let query = params.join("&");
+        new.set_query(Some(query.as_str()).filter(|q| !q.is_empty()));
+
+        new
+    }
+}
+
+// Decodes escapes of unreserved characters and upper cases the hex digits of the rest as
+// RFC 3986 section 6.2.2 describes.
+fn normalise_percent_encoding(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        match escape.and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()) {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                out.push(byte as char);
+                i += 3;
+            }
+            Some(byte) => {
+                out.push_str(&format!("%{:02X}", byte));
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i] as char);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canonicalizer, QueryOrder, QueryPolicy, TrailingSlash};
+    use reqwest::Url;
+
+    fn canonicalize(canonicalizer: &Canonicalizer, url: &str) -> String {
+        canonicalizer
+            .canonicalize(&Url::parse(url).unwrap())
+            .to_string()
+    }
+
+    #[test]
+    fn canonicalize_test() {
+        let canonicalizer = Canonicalizer::new();
+        for url in &[
+            "https://a.com/x",
+            "HTTPS://A.com/x",
+            "https://a.com:443/x",
+            "https://a.com/./x",
+            "https://a.com/y/../x",
+            "https://a.com/%78",
+            "https://a.com/x#top",
+            "https://a.com/x?utm_source=feed&fbclid=1",
+        ] {
+            assert_eq!(canonicalize(&canonicalizer, url), "https://a.com/x");
+        }
+        assert_eq!(
+            canonicalize(&canonicalizer, "https://a.com/%7euser/%2f?"),
+            "https://a.com/~user/%2F"
+        );
+
+        let canonicalizer = canonicalizer
+            .query(QueryOrder::Sort)
+            .trailing_slash(TrailingSlash::Add);
+        assert_eq!(
+            canonicalize(&canonicalizer, "https://a.com/x?b=1&a=2&b=0"),
+            "https://a.com/x/?a=2&b=1&b=0"
+        );
+        assert_eq!(
+            canonicalize(&canonicalizer, "https://a.com/x.html"),
+            "https://a.com/x.html"
+        );
+
+        let canonicalizer = canonicalizer
+            .query_params(QueryPolicy::Allow(vec!["page".to_owned()]))
+            .trailing_slash(TrailingSlash::Remove);
+        assert_eq!(
+            canonicalize(&canonicalizer, "https://a.com/x/?sort=a&page=2"),
+            "https://a.com/x?page=2"
+        );
+
+        let canonicalizer = canonicalizer.query_params(QueryPolicy::DropAll);
+        assert_eq!(
+            canonicalize(&canonicalizer, "https://a.com/x/?a=1"),
+            "https://a.com/x"
+        );
+        assert_eq!(
+            canonicalize(&canonicalizer, "https://a.com/"),
+            "https://a.com/"
+        );
+    }
+}
@@ -0,0 +1,869 @@
+use super::fetch::ResponseInfo;
+use super::store::CrawlStore;
+use super::{
+    ChangeFreq, CrawlError, CrawlerConfig, Limit, Link, LinkKind, Page, PageMetadata, PageState,
+    Redirect, RobotsDirectives, SimpleCrawler, SitemapEntry, SkipReason, StoredBody,
+};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Lists are stored one item per line. Urls and header values can't hold a newline.
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+CREATE TABLE IF NOT EXISTS crawl (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    base_url TEXT NOT NULL,
+    limit_reached TEXT,
+    sitemaps TEXT
+);
+CREATE TABLE IF NOT EXISTS aliases (
+    url TEXT PRIMARY KEY,
+    canonical_url TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS query_variants (
+    path TEXT PRIMARY KEY,
+    count INTEGER NOT NULL
+);
+";
+
+// Shared with `SqliteStore` which keeps pages the same way.
+pub(crate) const PAGES_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS pages (
+    url TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    reason TEXT,
+    depth INTEGER NOT NULL,
+    referrers TEXT NOT NULL,
+    link_source TEXT,
+    link_text TEXT,
+    link_rel TEXT,
+    link_kind TEXT,
+    canonical_url TEXT,
+    noindex INTEGER NOT NULL,
+    nofollow INTEGER NOT NULL,
+    sitemap TEXT,
+    lastmod TEXT,
+    changefreq TEXT,
+    priority REAL,
+    attempts INTEGER NOT NULL,
+    status INTEGER,
+    final_url TEXT,
+    headers TEXT,
+    content_type TEXT,
+    content_length INTEGER,
+    latency_ns INTEGER,
+    fetched_at_ns INTEGER,
+    retry_after_ns INTEGER,
+    robots_tags TEXT,
+    metadata TEXT,
+    data TEXT NOT NULL,
+    body TEXT,
+    body_digest TEXT,
+    body_path TEXT,
+    redirects TEXT
+);
+";
+
+pub(crate) const INSERT_PAGE: &str = "INSERT OR REPLACE INTO pages VALUES (
+    ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+    ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28,
+    ?29, ?30, ?31, ?32
+)";
+
+/// A crawl saved to an SQLite database as it runs so it can be carried on with
+/// `SimpleCrawler::resume` after it's interrupted. Pages are written as soon as they change so
+/// only the requests in flight when the crawl stopped are lost. Bodies are only saved if the
+/// crawl's `BodyRetention` keeps them in memory. Bodies kept on disk are saved as their file.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    conn: Connection,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at a path, creating it if it doesn't exist.
+    pub(crate) fn open(path: &Path) -> Result<Self, CrawlError> {
+        let conn = Connection::open(path).map_err(|e| error(path, e))?;
+        conn.execute_batch(SCHEMA)
+            .and_then(|_| conn.execute_batch(PAGES_SCHEMA))
+            .map_err(|e| error(path, e))?;
+        Ok(Checkpoint {
+            path: path.to_owned(),
+            conn,
+        })
+    }
+
+    /// Opens the checkpoint set with `CrawlerBuilder::checkpoint` if there is one.
+    pub(crate) fn from_config(config: &CrawlerConfig) -> Result<Option<Self>, CrawlError> {
+        config
+            .checkpoint
+            .as_deref()
+            .map(Checkpoint::open)
+            .transpose()
+    }
+
+    /// Replaces everything in the checkpoint with the crawler's state.
+    fn save_all<S: CrawlStore>(&mut self, crawler: &SimpleCrawler<S>) -> Result<(), CrawlError> {
+        self.write(crawler, true, crawler.urls.iter(), crawler.aliases.keys())
+    }
+
+    /// Writes the pages and aliases for these urls and what's known about the crawl as a whole.
+    fn save<'a, S: CrawlStore>(
+        &mut self,
+        crawler: &SimpleCrawler<S>,
+        urls: impl Iterator<Item = &'a String>,
+        aliases: impl Iterator<Item = &'a String>,
+    ) -> Result<(), CrawlError> {
+        let pages = urls.filter_map(|url| {
+            let page = crawler.urls.get(url).transpose()?;
+            Some(page.map(|page| (url.to_owned(), page)))
+        });
+        self.write(crawler, false, pages, aliases)
+    }
+
+    // Writes in one transaction. The first error reading a page from the store is returned as it
+    // is and nothing is written.
+    fn write<'a, S: CrawlStore>(
+        &mut self,
+        crawler: &SimpleCrawler<S>,
+        replace: bool,
+        pages: impl Iterator<Item = Result<(String, Page), CrawlError>>,
+        aliases: impl Iterator<Item = &'a String>,
+    ) -> Result<(), CrawlError> {
+        let path = self.path.to_owned();
+        let sql = |e| error(&path, e);
+        let tx = self.conn.transaction().map_err(sql)?;
+        if replace {
+            tx.execute_batch("DELETE FROM pages; DELETE FROM aliases; DELETE FROM query_variants;")
+                .map_err(sql)?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO crawl VALUES (0, ?1, ?2, ?3)",
+            params![
+                crawler.base_url,
+                crawler.limit_reached.map(limit_name),
+                crawler.sitemaps.as_ref().map(|s| s.join("\n")),
+            ],
+        )
+        .map_err(sql)?;
+        {
+            let mut insert_page = tx.prepare_cached(INSERT_PAGE).map_err(sql)?;
+            let mut insert_alias = tx
+                .prepare_cached("INSERT OR REPLACE INTO aliases VALUES (?1, ?2)")
+                .map_err(sql)?;
+            let mut insert_variants = tx
+                .prepare_cached("INSERT OR REPLACE INTO query_variants VALUES (?1, ?2)")
+                .map_err(sql)?;
+            for page in pages {
+                let (url, page) = page?;
+                write_page(&mut insert_page, &url, &page).map_err(sql)?;
+                let variants = url
+                    .split_once('?')
+                    .and_then(|(path, _)| Some((path, crawler.query_variants.get(path)?)));
+                if let Some((path, count)) = variants {
+                    insert_variants
+                        .execute(params![path, *count as i64])
+                        .map_err(sql)?;
+                }
+            }
+            for url in aliases {
+                if let Some(canonical) = crawler.aliases.get(url) {
+                    insert_alias.execute(params![url, canonical]).map_err(sql)?;
+                }
+            }
+        }
+        tx.commit().map_err(sql)
+    }
+
+    /// Reads the checkpointed crawl into the crawler. Pages with the same url are replaced.
+    fn load<S: CrawlStore>(
+        &self,
+        crawler: SimpleCrawler<S>,
+    ) -> Result<SimpleCrawler<S>, CrawlError> {
+        let sql = |e| error(&self.path, e);
+        let mut new = crawler;
+        let (base_url, limit_reached, sitemaps) = self
+            .conn
+            .query_row(
+                "SELECT base_url, limit_reached, sitemaps FROM crawl",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .map_err(sql)?;
+        new.base_url = base_url;
+        new.limit_reached = match limit_reached {
+            Some(limit) => Some(parse_limit(&limit).ok_or_else(|| sql(invalid(1, &limit)))?),
+            None => None,
+        };
+        new.sitemaps = sitemaps.map(|s| lines(&s));
+
+        let mut pages = self.conn.prepare("SELECT * FROM pages").map_err(sql)?;
+        for page in pages.query_map([], read_page).map_err(sql)? {
+            let (url, page) = page.map_err(sql)?;
+            new.urls.insert(&url, page)?;
+        }
+        let mut aliases = self
+            .conn
+            .prepare("SELECT url, canonical_url FROM aliases")
+            .map_err(sql)?;
+        let rows = aliases
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(sql)?;
+        for alias in rows {
+            let (url, canonical) = alias.map_err(sql)?;
+            new.aliases.insert(url, canonical);
+        }
+        let mut variants = self
+            .conn
+            .prepare("SELECT path, count FROM query_variants")
+            .map_err(sql)?;
+        let rows = variants
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)?)))
+            .map_err(sql)?;
+        for variant in rows {
+            let (path, count) = variant.map_err(sql)?;
+            new.query_variants.insert(path, count as usize);
+        }
+
+        Ok(new)
+    }
+}
+
+fn error(path: &Path, e: rusqlite::Error) -> CrawlError {
+    CrawlError::Checkpoint {
+        path: path.to_owned(),
+        source: Arc::new(e),
+    }
+}
+
+fn invalid(column: usize, value: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(
+        column,
+        Type::Text,
+        format!("unknown value {}", value).into(),
+    )
+}
+
+fn lines(s: &str) -> Vec<String> {
+    s.lines().map(|l| l.to_owned()).collect()
+}
+
+// Handler data can hold anything so tabs and line breaks are escaped to keep one item per line.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+// Metadata is stored one item per line as its field and values separated by tabs.
+fn metadata_lines(metadata: &PageMetadata) -> String {
+    let item = |field: &str, values: &[&str]| {
+        let values = values.iter().map(|v| escape(v)).collect::<Vec<_>>();
+        format!("{}\t{}", field, values.join("\t"))
+    };
+    let single = [
+        ("title", &metadata.title),
+        ("description", &metadata.description),
+        ("robots", &metadata.robots),
+        ("canonical", &metadata.canonical),
+    ];
+    let pairs = [
+        ("hreflang", &metadata.hreflang),
+        ("open_graph", &metadata.open_graph),
+        ("twitter", &metadata.twitter),
+    ];
+    let items = single
+        .iter()
+        .filter_map(|(field, value)| Some(item(field, &[value.as_deref()?])))
+        .chain(
+            metadata
+                .headings
+                .iter()
+                .map(|(level, text)| item(&format!("h{}", level), &[text])),
+        )
+        .chain(pairs.iter().flat_map(|(field, pairs)| {
+            pairs
+                .iter()
+                .map(move |(name, value)| item(field, &[name, value]))
+        }))
+        .chain(metadata.json_ld.iter().map(|j| item("json_ld", &[j])))
+        .collect::<Vec<_>>();
+    items.join("\n")
+}
+
+fn parse_metadata(s: &str) -> PageMetadata {
+    let mut metadata = PageMetadata::default();
+    for line in s.lines() {
+        let values = line.split('\t').map(unescape).collect::<Vec<_>>();
+        match (values[0].as_str(), &values[1..]) {
+            ("title", [value]) => metadata.title = Some(value.to_owned()),
+            ("description", [value]) => metadata.description = Some(value.to_owned()),
+            ("robots", [value]) => metadata.robots = Some(value.to_owned()),
+            ("canonical", [value]) => metadata.canonical = Some(value.to_owned()),
+            ("h1", [text]) => metadata.headings.push((1, text.to_owned())),
+            ("h2", [text]) => metadata.headings.push((2, text.to_owned())),
+            ("h3", [text]) => metadata.headings.push((3, text.to_owned())),
+            ("hreflang", [lang, href]) => {
+                metadata.hreflang.push((lang.to_owned(), href.to_owned()))
+            }
+            ("open_graph", [name, value]) => metadata
+                .open_graph
+                .push((name.to_owned(), value.to_owned())),
+            ("twitter", [name, value]) => {
+                metadata.twitter.push((name.to_owned(), value.to_owned()))
+            }
+            ("json_ld", [value]) => metadata.json_ld.push(value.to_owned()),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next() {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some(c) => c,
+                None => break,
+            },
+            c => c,
+        });
+    }
+    unescaped
+}
+
+pub(crate) fn write_page(
+    insert: &mut rusqlite::CachedStatement,
+    url: &str,
+    page: &Page,
+) -> rusqlite::Result<()> {
+    let (state, reason) = match &page.state {
+        PageState::Pending => ("pending", None),
+        PageState::Fetched => ("fetched", None),
+        PageState::Failed(e) => ("failed", Some(error_reason(e))),
+        PageState::Skipped(reason) => ("skipped", Some(skip_reason(reason))),
+    };
+    let link = page.discovered_by.as_ref();
+    let sitemap = page.sitemap.as_ref();
+    let response = page.response.as_ref();
+    let stored_body = page.stored_body.as_ref();
+    let nanos = |d: Duration| d.as_nanos() as i64;
+
+    insert.execute(params![
+        url,
+        state,
+        reason,
+        page.depth,
+        page.referrers.join("\n"),
+        link.map(|l| &l.source),
+        link.map(|l| &l.text),
+        link.map(|l| l.rel.join(" ")),
+        link.map(|l| link_kind_name(l.kind)),
+        page.canonical_url,
+        page.robots_directives.noindex,
+        page.robots_directives.nofollow,
+        sitemap.map(|s| &s.sitemap),
+        sitemap.and_then(|s| s.lastmod.as_ref()),
+        sitemap.and_then(|s| s.changefreq).map(change_freq_name),
+        sitemap.and_then(|s| s.priority),
+        page.attempts,
+        response.map(|r| r.status),
+        response.map(|r| &r.final_url),
+        response.map(|r| {
+            r.headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+        response.and_then(|r| r.content_type.as_ref()),
+        response.and_then(|r| r.content_length).map(|l| l as i64),
+        response.map(|r| nanos(r.latency)),
+        response.map(|r| nanos(r.fetched_at.duration_since(UNIX_EPOCH).unwrap_or_default())),
+        response.and_then(|r| r.retry_after).map(nanos),
+        response.map(|r| r.robots_tags.join("\n")),
+        page.metadata.as_ref().map(metadata_lines),
+        page.data
+            .iter()
+            .map(|(name, value)| format!("{}\t{}", escape(name), escape(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        match &page.stored_body {
+            Some(StoredBody::Memory(body)) => Some(body),
+            _ => None,
+        },
+        stored_body.and_then(|b| b.digest()),
+        stored_body
+            .and_then(|b| b.path())
+            .map(|p| p.to_string_lossy()),
+        response.map(|r| {
+            r.redirects
+                .iter()
+                .map(|hop| {
+                    format!(
+                        "{}\t{}\t{}",
+                        hop.status,
+                        escape(&hop.url),
+                        escape(&hop.location)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+    ])?;
+    Ok(())
+}
+
+pub(crate) fn read_page(row: &Row) -> rusqlite::Result<(String, Page)> {
+    let url: String = row.get(0)?;
+    let state: String = row.get(1)?;
+    let reason: Option<String> = row.get(2)?;
+    let state = match (state.as_str(), reason.as_deref()) {
+        ("pending", _) => PageState::Pending,
+        ("fetched", _) => PageState::Fetched,
+        ("failed", Some(reason)) => PageState::Failed(parse_error(&url, reason)),
+        ("skipped", Some(reason)) => {
+            PageState::Skipped(parse_skip_reason(reason).ok_or_else(|| invalid(2, reason))?)
+        }
+        (state, _) => return Err(invalid(1, state)),
+    };
+
+    let discovered_by = match row.get::<_, Option<String>>(5)? {
+        Some(source) => {
+            let kind: String = row.get(8)?;
+            Some(Link {
+                source,
+                text: row.get(6)?,
+                rel: row
+                    .get::<_, String>(7)?
+                    .split_whitespace()
+                    .map(|r| r.to_owned())
+                    .collect(),
+                kind: parse_link_kind(&kind).ok_or_else(|| invalid(8, &kind))?,
+            })
+        }
+        None => None,
+    };
+
+    let sitemap = match row.get::<_, Option<String>>(12)? {
+        Some(sitemap) => Some(SitemapEntry {
+            sitemap,
+            lastmod: row.get(13)?,
+            changefreq: row
+                .get::<_, Option<String>>(14)?
+                .as_deref()
+                .and_then(ChangeFreq::parse),
+            priority: row.get(15)?,
+        }),
+        None => None,
+    };
+
+    let nanos = |ns: i64| Duration::from_nanos(ns as u64);
+    let response = match row.get::<_, Option<u16>>(17)? {
+        Some(status) => Some(ResponseInfo {
+            status,
+            final_url: row.get(18)?,
+            headers: lines(&row.get::<_, String>(19)?)
+                .iter()
+                .filter_map(|h| h.split_once(": "))
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+            content_type: row.get(20)?,
+            content_length: row.get::<_, Option<i64>>(21)?.map(|l| l as u64),
+            latency: nanos(row.get(22)?),
+            fetched_at: UNIX_EPOCH + nanos(row.get(23)?),
+            retry_after: row.get::<_, Option<i64>>(24)?.map(nanos),
+            robots_tags: lines(&row.get::<_, String>(25)?),
+            redirects: lines(&row.get::<_, Option<String>>(31)?.unwrap_or_default())
+                .iter()
+                .filter_map(|hop| {
+                    let mut fields = hop.split('\t');
+                    Some(Redirect {
+                        status: fields.next()?.parse().ok()?,
+                        url: unescape(fields.next()?),
+                        location: unescape(fields.next()?),
+                    })
+                })
+                .collect(),
+        }),
+        None => None,
+    };
+
+    let page = Page {
+        state,
+        depth: row.get(3)?,
+        referrers: lines(&row.get::<_, String>(4)?),
+        discovered_by,
+        canonical_url: row.get(9)?,
+        robots_directives: RobotsDirectives {
+            noindex: row.get(10)?,
+            nofollow: row.get(11)?,
+        },
+        sitemap,
+        attempts: row.get(16)?,
+        response,
+        metadata: row
+            .get::<_, Option<String>>(26)?
+            .map(|m| parse_metadata(&m)),
+        data: lines(&row.get::<_, String>(27)?)
+            .iter()
+            .filter_map(|d| d.split_once('\t'))
+            .map(|(name, value)| (unescape(name), unescape(value)))
+            .collect(),
+        stored_body: match (
+            row.get(28)?,
+            row.get(29)?,
+            row.get::<_, Option<String>>(30)?,
+        ) {
+            (Some(body), _, _) => Some(StoredBody::Memory(body)),
+            (None, Some(digest), Some(path)) => Some(StoredBody::Disk {
+                digest,
+                path: PathBuf::from(path),
+            }),
+            _ => None,
+        },
+        ..Page::new()
+    };
+    Ok((url, page))
+}
+
+// Errors a page can fail with are saved as their kind and what's needed to rebuild them so
+// retries and reports treat them the same once they're read back. Anything else is saved as its
+// message.
+fn error_reason(e: &CrawlError) -> String {
+    match e.detail() {
+        Some(detail) if detail.is_empty() => e.kind().to_owned(),
+        Some(detail) => format!("{} {}", e.kind(), detail),
+        None => format!("error {}", e),
+    }
+}
+
+fn parse_error(url: &str, reason: &str) -> CrawlError {
+    let (kind, detail) = reason.split_once(' ').unwrap_or((reason, ""));
+    CrawlError::restore(kind, url, detail).unwrap_or_else(|| CrawlError::Restored {
+        url: url.to_owned(),
+        message: detail.to_owned(),
+    })
+}
+
+pub(crate) fn skip_reason(reason: &SkipReason) -> String {
+    match reason {
+        SkipReason::RobotsDisallowed => "robots_disallowed".to_owned(),
+        SkipReason::Limit(limit) => format!("limit {}", limit_name(*limit)),
+        SkipReason::NotFollowed => "not_followed".to_owned(),
+        SkipReason::NoFollow => "nofollow".to_owned(),
+        SkipReason::Redirected => "redirected".to_owned(),
+    }
+}
+
+fn parse_skip_reason(reason: &str) -> Option<SkipReason> {
+    match reason.split_once(' ') {
+        Some(("limit", limit)) => parse_limit(limit).map(SkipReason::Limit),
+        _ => match reason {
+            "robots_disallowed" => Some(SkipReason::RobotsDisallowed),
+            "not_followed" => Some(SkipReason::NotFollowed),
+            "nofollow" => Some(SkipReason::NoFollow),
+            "redirected" => Some(SkipReason::Redirected),
+            _ => None,
+        },
+    }
+}
+
+fn limit_name(limit: Limit) -> &'static str {
+    match limit {
+        Limit::MaxPages => "max_pages",
+        Limit::MaxDepth => "max_depth",
+        Limit::MaxDuration => "max_duration",
+        Limit::MaxBytes => "max_bytes",
+        Limit::MaxQueryVariants => "max_query_variants",
+    }
+}
+
+fn parse_limit(name: &str) -> Option<Limit> {
+    match name {
+        "max_pages" => Some(Limit::MaxPages),
+        "max_depth" => Some(Limit::MaxDepth),
+        "max_duration" => Some(Limit::MaxDuration),
+        "max_bytes" => Some(Limit::MaxBytes),
+        "max_query_variants" => Some(Limit::MaxQueryVariants),
+        _ => None,
+    }
+}
+
+pub(crate) fn link_kind_name(kind: LinkKind) -> &'static str {
+    match kind {
+        LinkKind::Anchor => "anchor",
+        LinkKind::Area => "area",
+        LinkKind::Link => "link",
+        LinkKind::Frame => "frame",
+        LinkKind::Image => "image",
+        LinkKind::Script => "script",
+        LinkKind::Source => "source",
+        LinkKind::Form => "form",
+        LinkKind::MetaRefresh => "meta_refresh",
+        LinkKind::Css => "css",
+        LinkKind::Handler => "handler",
+    }
+}
+
+fn parse_link_kind(name: &str) -> Option<LinkKind> {
+    LinkKind::ALL
+        .iter()
+        .copied()
+        .find(|kind| link_kind_name(*kind) == name)
+}
+
+pub(crate) fn change_freq_name(changefreq: ChangeFreq) -> &'static str {
+    match changefreq {
+        ChangeFreq::Always => "always",
+        ChangeFreq::Hourly => "hourly",
+        ChangeFreq::Daily => "daily",
+        ChangeFreq::Weekly => "weekly",
+        ChangeFreq::Monthly => "monthly",
+        ChangeFreq::Yearly => "yearly",
+        ChangeFreq::Never => "never",
+    }
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// Carries on a crawl which was checkpointed with `CrawlerBuilder::checkpoint` for example
+    /// `let simple_crawler = SimpleCrawler::builder().build().resume(path)?.crawl()`. Fetched and
+    /// failed pages aren't requested again. Urls skipped because the page, time or byte limit of
+    /// the earlier crawl was reached are pending again. The settings aren't saved so they should
+    /// be set on the builder again, and the resumed crawl carries on writing to the checkpoint.
+    pub fn resume<P: AsRef<Path>>(self, path: P) -> Result<Self, CrawlError> {
+        let path = path.as_ref();
+        let checkpoint = Checkpoint::open(path)?;
+        let mut new = checkpoint.load(self)?;
+
+        let mut per_crawl = vec![];
+        for page in new.urls.iter() {
+            let (url, page) = page?;
+            if matches!(
+                page.skip_reason(),
+                Some(SkipReason::Limit(
+                    Limit::MaxPages | Limit::MaxDuration | Limit::MaxBytes
+                ))
+            ) {
+                per_crawl.push(url);
+            }
+        }
+        for url in per_crawl {
+            new.urls.mark_state(&url, PageState::Pending)?;
+        }
+        new.limit_reached = None;
+        new.config.checkpoint = Some(path.to_owned());
+
+        Ok(new)
+    }
+
+    /// Writes the whole crawl to the checkpoint, replacing anything from an earlier crawl.
+    pub(crate) fn checkpoint_all(
+        &mut self,
+        checkpoint: &mut Option<Checkpoint>,
+    ) -> Result<(), CrawlError> {
+        self.changed = checkpoint.as_ref().map(|_| HashSet::new());
+        self.changed_aliases = checkpoint.as_ref().map(|_| HashSet::new());
+        match checkpoint.as_mut() {
+            Some(checkpoint) => checkpoint.save_all(self),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the pages and aliases which changed since the last write to the checkpoint.
+    pub(crate) fn checkpoint_changed(
+        &mut self,
+        checkpoint: &mut Option<Checkpoint>,
+    ) -> Result<(), CrawlError> {
+        let changed = self.changed.replace(HashSet::new()).unwrap_or_default();
+        let aliases = self
+            .changed_aliases
+            .replace(HashSet::new())
+            .unwrap_or_default();
+        match checkpoint.as_mut() {
+            Some(checkpoint) => checkpoint.save(self, changed.iter(), aliases.iter()),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the last changes to the checkpoint once the crawl has finished and stops tracking
+    /// them.
+    pub(crate) fn checkpoint_finished(
+        &mut self,
+        checkpoint: Option<Checkpoint>,
+    ) -> Result<(), CrawlError> {
+        let mut checkpoint = checkpoint;
+        let result = self.checkpoint_changed(&mut checkpoint);
+        self.changed = None;
+        self.changed_aliases = None;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checkpoint;
+    use crate::fetch::ResponseInfo;
+    use crate::{
+        CrawlError, CrawlStore, Limit, Link, LinkKind, Page, PageMetadata, PageState, Redirect,
+        SimpleCrawler, SkipReason, StoredBody,
+    };
+    use std::error::Error;
+    use std::io;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn checkpoint_test() -> Result<(), CrawlError> {
+        let dir = std::env::temp_dir().join(format!("checkpoint_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("crawl.db");
+
+        let mut simple_crawler = SimpleCrawler::new()
+            .url("https://test.com/a")?
+            .url("https://test.com/b")?
+            .url("https://test.com/c")?;
+        simple_crawler.urls.insert(
+            "https://test.com/b",
+            Page {
+                state: PageState::Failed(CrawlError::Status {
+                    url: "https://test.com/b".to_owned(),
+                    status: 503,
+                }),
+                depth: 1,
+                referrers: vec!["https://test.com/a".to_owned()],
+                discovered_by: Some(Link::new(
+                    "https://test.com/a",
+                    "b",
+                    Some("next"),
+                    LinkKind::Anchor,
+                )),
+                attempts: 3,
+                response: Some(ResponseInfo {
+                    status: 503,
+                    final_url: "https://test.com/b/".to_owned(),
+                    headers: vec![("server".to_owned(), "test".to_owned())],
+                    content_type: None,
+                    content_length: Some(0),
+                    latency: Duration::from_millis(20),
+                    fetched_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+                    retry_after: None,
+                    robots_tags: vec![],
+                    redirects: vec![Redirect {
+                        url: "https://test.com/b".to_owned(),
+                        status: 301,
+                        location: "https://test.com/b/".to_owned(),
+                    }],
+                }),
+                metadata: Some(PageMetadata {
+                    title: Some("B".to_owned()),
+                    headings: vec![(1, "B".to_owned()), (2, "About\tus".to_owned())],
+                    hreflang: vec![("fr".to_owned(), "https://test.com/fr/b".to_owned())],
+                    json_ld: vec!["{\n  \"@type\": \"Thing\"\n}".to_owned()],
+                    ..PageMetadata::default()
+                }),
+                data: vec![
+                    ("price".to_owned(), "9.99".to_owned()),
+                    ("notes".to_owned(), "a\tb\\n\nc".to_owned()),
+                ]
+                .into_iter()
+                .collect(),
+                stored_body: Some(StoredBody::Disk {
+                    digest: "ab12".to_owned(),
+                    path: "bodies/ab/ab12.gz".into(),
+                }),
+                ..Page::new()
+            },
+        )?;
+        simple_crawler.urls.insert(
+            "https://test.com/c",
+            Page::skipped(SkipReason::Limit(Limit::MaxPages)),
+        )?;
+        simple_crawler
+            .urls
+            .insert("https://test.com/b/", Page::skipped(SkipReason::Redirected))?;
+        let failed = |state| Page {
+            state,
+            attempts: 3,
+            ..Page::new()
+        };
+        let timeout = CrawlError::Timeout {
+            url: "https://test.com/d".to_owned(),
+            source: Arc::new(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "operation timed out",
+            )),
+        };
+        let looped = CrawlError::RedirectLoop {
+            url: "https://test.com/e".to_owned(),
+        };
+        simple_crawler
+            .urls
+            .insert("https://test.com/d", failed(PageState::Failed(timeout)))?;
+        simple_crawler
+            .urls
+            .insert("https://test.com/e", failed(PageState::Failed(looped)))?;
+        let mut checkpoint = Some(Checkpoint::open(&path)?);
+        simple_crawler.checkpoint_all(&mut checkpoint)?;
+        drop(checkpoint);
+
+        let resumed = SimpleCrawler::new().resume(&path)?;
+        assert_eq!(resumed.urls.len(), 6);
+        // Failures are read back as the kind of error they were.
+        for url in ["https://test.com/d", "https://test.com/e"].iter() {
+            assert_eq!(resumed.urls[*url], simple_crawler.urls[*url]);
+        }
+        let timeout = resumed.urls["https://test.com/d"].error();
+        assert!(timeout.is_some_and(|e| e.is_retryable()));
+        assert_eq!(
+            timeout.and_then(|e| e.source()).map(|s| s.to_string()),
+            Some("operation timed out".to_owned())
+        );
+        assert_eq!(
+            resumed.urls["https://test.com/b"],
+            simple_crawler.urls["https://test.com/b"]
+        );
+        assert!(resumed.urls["https://test.com/c"].is_pending());
+        assert_eq!(
+            resumed.urls["https://test.com/b/"].skip_reason(),
+            Some(&SkipReason::Redirected)
+        );
+        assert_eq!(resumed.config().checkpoint(), Some(path.as_path()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoint_error_test() -> Result<(), CrawlError> {
+        let dir = std::env::temp_dir().join(format!("checkpoint_error_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("crawl.db");
+
+        // A checkpoint which can't be written is returned as an error rather than left behind.
+        let mut simple_crawler = SimpleCrawler::new().url("https://test.com/a")?;
+        let checkpoint = Checkpoint::open(&path)?;
+        checkpoint.conn.execute_batch("DROP TABLE pages").unwrap();
+        let result = simple_crawler.checkpoint_all(&mut Some(checkpoint));
+        assert!(matches!(result, Err(CrawlError::Checkpoint { .. })));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,397 @@
+use super::handler::Handler;
+use super::{
+    Archive, BodyRetention, Canonicalizer, CrawlStore, LinkKind, MemoryStore, PageHandler,
+    Politeness, RedirectPolicy, RetryPolicy, ScopePolicy, SimpleCrawler, DEFAULT_RECORD_HEADERS,
+    DEFAULT_USER_AGENT,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a request can take when it hasn't been set with `CrawlerBuilder::request_timeout`.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The CrawlerConfig struct holds the settings a crawl runs with. It's built with
+/// `SimpleCrawler::builder()` and can be read back with `SimpleCrawler::config()`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrawlerConfig {
+    pub(crate) user_agent: String,
+    pub(crate) record_headers: Vec<String>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) redirects: RedirectPolicy,
+    pub(crate) politeness: Politeness,
+    pub(crate) scope: ScopePolicy,
+    pub(crate) canonicalizer: Canonicalizer,
+    pub(crate) extract_links: Vec<LinkKind>,
+    pub(crate) follow_links: Vec<LinkKind>,
+    pub(crate) obey_robots_directives: bool,
+    pub(crate) extract_metadata: bool,
+    pub(crate) sitemaps: bool,
+    pub(crate) checkpoint: Option<PathBuf>,
+    pub(crate) max_pages: Option<usize>,
+    pub(crate) max_depth: Option<u32>,
+    pub(crate) max_duration: Option<Duration>,
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) max_body_size: Option<u64>,
+    pub(crate) max_query_variants: Option<usize>,
+    pub(crate) request_timeout: Duration,
+    pub(crate) body_retention: BodyRetention,
+    pub(crate) archive: Option<Archive>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) handler: Option<Handler>,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        CrawlerConfig {
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            record_headers: DEFAULT_RECORD_HEADERS
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+            retry: RetryPolicy::new(),
+            redirects: RedirectPolicy::new(),
+            politeness: Politeness::new(),
+            scope: ScopePolicy::same_host(),
+            canonicalizer: Canonicalizer::new(),
+            extract_links: LinkKind::ALL.to_vec(),
+            follow_links: LinkKind::PAGES.to_vec(),
+            obey_robots_directives: true,
+            extract_metadata: false,
+            sitemaps: false,
+            checkpoint: None,
+            max_pages: None,
+            max_depth: None,
+            max_duration: None,
+            max_bytes: None,
+            max_body_size: None,
+            max_query_variants: None,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            body_retention: BodyRetention::Discard,
+            archive: None,
+            handler: None,
+        }
+    }
+}
+
+impl CrawlerConfig {
+    /// The user agent sent with every request.
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// The response headers kept on each `Page`.
+    pub fn record_headers(&self) -> &[String] {
+        &self.record_headers
+    }
+
+    /// How failed requests are retried.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// How redirects are followed.
+    pub fn redirect_policy(&self) -> &RedirectPolicy {
+        &self.redirects
+    }
+
+    /// How hard each host is worked.
+    pub fn politeness(&self) -> &Politeness {
+        &self.politeness
+    }
+
+    /// Which urls the crawl follows.
+    pub fn scope(&self) -> &ScopePolicy {
+        &self.scope
+    }
+
+    /// How urls are rewritten before they're used as keys.
+    pub fn canonicalizer(&self) -> &Canonicalizer {
+        &self.canonicalizer
+    }
+
+    /// The kinds of link looked for on each page.
+    pub fn extract_links(&self) -> &[LinkKind] {
+        &self.extract_links
+    }
+
+    /// The kinds of link which are crawled. Other kinds are recorded but never requested.
+    pub fn follow_links(&self) -> &[LinkKind] {
+        &self.follow_links
+    }
+
+    /// Whether nofollow links and robots directives are obeyed rather than only recorded.
+    pub fn obey_robots_directives(&self) -> bool {
+        self.obey_robots_directives
+    }
+
+    /// Whether each fetched page's title, description, headings and other metadata are recorded.
+    pub fn extract_metadata(&self) -> bool {
+        self.extract_metadata
+    }
+
+    /// Whether the site's sitemaps are read for seed urls.
+    pub fn sitemaps(&self) -> bool {
+        self.sitemaps
+    }
+
+    /// Where the crawl is checkpointed.
+    pub fn checkpoint(&self) -> Option<&Path> {
+        self.checkpoint.as_deref()
+    }
+
+    /// The most urls requested in a crawl.
+    pub fn max_pages(&self) -> Option<usize> {
+        self.max_pages
+    }
+
+    /// The most links followed away from a seed url.
+    pub fn max_depth(&self) -> Option<u32> {
+        self.max_depth
+    }
+
+    /// The longest a crawl runs for.
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration
+    }
+
+    /// The most body bytes downloaded in a crawl.
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// The largest response body read for a single url.
+    pub fn max_body_size(&self) -> Option<u64> {
+        self.max_body_size
+    }
+
+    /// The most urls with a query crawled for a single path.
+    pub fn max_query_variants(&self) -> Option<usize> {
+        self.max_query_variants
+    }
+
+    /// The longest a single request can take, from connecting until its body has been read.
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// What's done with each fetched page's body once its links have been found.
+    pub fn body_retention(&self) -> &BodyRetention {
+        &self.body_retention
+    }
+
+    /// Where the crawl is archived as WARC files.
+    pub fn archive(&self) -> Option<&Archive> {
+        self.archive.as_ref()
+    }
+
+    /// What's called with each fetched page.
+    pub fn page_handler(&self) -> Option<&dyn PageHandler> {
+        self.handler.as_ref().map(|h| h.0.as_ref())
+    }
+}
+
+/// The CrawlerBuilder struct sets up a `SimpleCrawler` for example
+/// `let simple_crawler = SimpleCrawler::builder().max_pages(100).build().url(&str)`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrawlerBuilder {
+    config: CrawlerConfig,
+}
+
+impl CrawlerBuilder {
+    /// Sets the user agent sent with every request and used to pick the robots.txt group.
+    pub fn user_agent(self, user_agent: &str) -> Self {
+        let mut new = self;
+        new.config.user_agent = user_agent.to_owned();
+        new
+    }
+
+    /// Sets which response headers are kept on each `Page`, replacing `DEFAULT_RECORD_HEADERS`.
+    pub fn record_headers(self, headers: &[&str]) -> Self {
+        let mut new = self;
+        new.config.record_headers = headers.iter().map(|h| h.to_lowercase()).collect();
+        new
+    }
+
+    /// Sets how failed requests are retried.
+    pub fn retry_policy(self, retry: RetryPolicy) -> Self {
+        let mut new = self;
+        new.config.retry = retry;
+        new
+    }
+
+    /// Sets how many redirects are followed and whether they're followed out of the crawl's
+    /// scope.
+    pub fn redirect_policy(self, redirects: RedirectPolicy) -> Self {
+        let mut new = self;
+        new.config.redirects = redirects;
+        new
+    }
+
+    /// Sets the rate, delay and per host concurrency requests are sent with.
+    pub fn politeness(self, politeness: Politeness) -> Self {
+        let mut new = self;
+        new.config.politeness = politeness;
+        new
+    }
+
+    /// Sets which urls the crawl follows, replacing the default of only the base url's host.
+    pub fn scope(self, scope: ScopePolicy) -> Self {
+        let mut new = self;
+        new.config.scope = scope;
+        new
+    }
+
+    /// Sets how urls are rewritten before they're used as keys.
+    pub fn canonicalizer(self, canonicalizer: Canonicalizer) -> Self {
+        let mut new = self;
+        new.config.canonicalizer = canonicalizer;
+        new
+    }
+
+    /// Sets the kinds of link looked for on each page, replacing `LinkKind::ALL`.
+    pub fn extract_links(self, kinds: &[LinkKind]) -> Self {
+        let mut new = self;
+        new.config.extract_links = kinds.to_vec();
+        new
+    }
+
+    /// Sets the kinds of link which are crawled, replacing `LinkKind::PAGES`. Urls only found
+    /// through other kinds are recorded and skipped with `SkipReason::NotFollowed`.
+    pub fn follow_links(self, kinds: &[LinkKind]) -> Self {
+        let mut new = self;
+        new.config.follow_links = kinds.to_vec();
+        new
+    }
+
+    /// Sets whether links with `rel="nofollow"`, `ugc` or `sponsored` and the links on pages whose
+    /// `<meta name="robots">` or X-Robots-Tag header says `nofollow` are skipped with
+    /// `SkipReason::NoFollow`. This is on by default. When it's off they're followed but the rel
+    /// and directives are still recorded on each `Link` and `Page`.
+    pub fn obey_robots_directives(self, obey: bool) -> Self {
+        let mut new = self;
+        new.config.obey_robots_directives = obey;
+        new
+    }
+
+    /// Sets whether the title, meta description, meta robots, `<h1>` to `<h3>` headings,
+    /// canonical link, hreflang alternates, Open Graph and Twitter tags and JSON-LD of each
+    /// fetched page are recorded as its `PageMetadata`. This is off by default.
+    pub fn extract_metadata(self, extract: bool) -> Self {
+        let mut new = self;
+        new.config.extract_metadata = extract;
+        new
+    }
+
+    /// Sets whether the sitemaps listed in robots.txt and at `/sitemap.xml` on the base url's host
+    /// are read at the start of the crawl. Sitemap indexes are followed, gzipped sitemaps are
+    /// decompressed and every url listed which is in scope is added as a seed. Sitemaps are
+    /// requested with the crawl's `Politeness` and skipped if robots.txt disallows them. This is
+    /// off by default.
+    pub fn sitemaps(self, sitemaps: bool) -> Self {
+        let mut new = self;
+        new.config.sitemaps = sitemaps;
+        new
+    }
+
+    /// Saves the crawl to an SQLite database at this path as it runs so it can be carried on with
+    /// `SimpleCrawler::resume` if it's interrupted. Anything already in the database is replaced
+    /// when the crawl starts. The crawl stops with `CrawlError::Checkpoint` if it can't be
+    /// written.
+    pub fn checkpoint<P: AsRef<Path>>(self, path: P) -> Self {
+        let mut new = self;
+        new.config.checkpoint = Some(path.as_ref().to_owned());
+        new
+    }
+
+    /// Stops the crawl once this many urls have been requested.
+    pub fn max_pages(self, max_pages: usize) -> Self {
+        let mut new = self;
+        new.config.max_pages = Some(max_pages);
+        new
+    }
+
+    /// Skips urls found more than this many links away from a seed url. Seeds are at depth 0.
+    pub fn max_depth(self, max_depth: u32) -> Self {
+        let mut new = self;
+        new.config.max_depth = Some(max_depth);
+        new
+    }
+
+    /// Stops the crawl once it has been running this long. Requests still running then time out
+    /// and failed ones aren't retried if their backoff would run past it.
+    pub fn max_duration(self, max_duration: Duration) -> Self {
+        let mut new = self;
+        new.config.max_duration = Some(max_duration);
+        new
+    }
+
+    /// Stops the crawl once this many body bytes have been downloaded.
+    pub fn max_bytes(self, max_bytes: u64) -> Self {
+        let mut new = self;
+        new.config.max_bytes = Some(max_bytes);
+        new
+    }
+
+    /// Fails any url whose response body is larger than this many bytes without reading the rest
+    /// of it.
+    pub fn max_body_size(self, max_body_size: u64) -> Self {
+        let mut new = self;
+        new.config.max_body_size = Some(max_body_size);
+        new
+    }
+
+    /// Skips urls with a query once this many have been found for the same path, so faceted
+    /// searches and calendars can't trap the crawl.
+    pub fn max_query_variants(self, max_query_variants: usize) -> Self {
+        let mut new = self;
+        new.config.max_query_variants = Some(max_query_variants);
+        new
+    }
+
+    /// Sets the longest a single request can take, replacing `DEFAULT_REQUEST_TIMEOUT`. Connecting
+    /// and reading the body both count and a request which runs over fails with
+    /// `CrawlError::Timeout`, which is retried.
+    pub fn request_timeout(self, timeout: Duration) -> Self {
+        let mut new = self;
+        new.config.request_timeout = timeout;
+        new
+    }
+
+    /// Sets what's done with each fetched page's body once its links have been found, replacing
+    /// `BodyRetention::Discard`. Kept bodies are on `Page::stored_body`.
+    pub fn body_retention(self, retention: BodyRetention) -> Self {
+        let mut new = self;
+        new.config.body_retention = retention;
+        new
+    }
+
+    /// Archives every request the crawl makes and its response as WARC files.
+    pub fn archive(self, archive: Archive) -> Self {
+        let mut new = self;
+        new.config.archive = Some(archive);
+        new
+    }
+
+    /// Sets what's called with each fetched page to scrape it. Its data is kept on the page and
+    /// the urls it returns are crawled.
+    pub fn page_handler(self, handler: impl PageHandler + 'static) -> Self {
+        let mut new = self;
+        new.config.handler = Some(Handler::new(handler));
+        new
+    }
+
+    /// Creates the SimpleCrawler.
+    pub fn build(self) -> SimpleCrawler {
+        self.build_with_store(MemoryStore::new())
+    }
+
+    /// Creates the SimpleCrawler keeping its pages in a store for example
+    /// `let simple_crawler = SimpleCrawler::builder().build_with_store(SqliteStore::open(path)?)`
+    pub fn build_with_store<S: CrawlStore>(self, store: S) -> SimpleCrawler<S> {
+        SimpleCrawler {
+            config: self.config,
+            ..SimpleCrawler::with_store(store)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use select::document::Document;
+use select::predicate::Name;
+
+// X-Robots-Tag directives which take a value, so a name before a colon which isn't one of these
+// is a user agent the directives are for.
+const VALUE_DIRECTIVES: &[&str] = &[
+    "max-image-preview",
+    "max-snippet",
+    "max-video-preview",
+    "unavailable_after",
+];
+
+/// The RobotsDirectives struct holds what a page asked crawlers to do with it through
+/// `<meta name="robots">` or the X-Robots-Tag header. Directives for other user agents are
+/// ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RobotsDirectives {
+    pub(crate) noindex: bool,
+    pub(crate) nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Whether the page asked not to be indexed, with `noindex` or `none`.
+    pub fn noindex(&self) -> bool {
+        self.noindex
+    }
+
+    /// Whether the page asked for its links not to be followed, with `nofollow` or `none`.
+    pub fn nofollow(&self) -> bool {
+        self.nofollow
+    }
+
+    /// The directives of a page's `<meta name="robots">` elements and the ones named after the
+    /// user agent's product token.
+    pub(crate) fn from_document(document: &Document, user_agent: &str) -> Self {
+        let token = product_token(user_agent);
+        document
+            .find(Name("meta"))
+            .filter(|node| {
+                node.attr("name").is_some_and(|name| {
+                    let name = name.trim().to_lowercase();
+                    name == "robots" || name == token
+                })
+            })
+            .filter_map(|node| node.attr("content"))
+            .fold(Self::default(), |directives, content| {
+                directives.add(content)
+            })
+    }
+
+    /// The directives of a response's X-Robots-Tag headers. A header value may start with the user
+    /// agent it's for, for example `googlebot: noindex`.
+    pub(crate) fn from_headers(values: &[String], user_agent: &str) -> Self {
+        let token = product_token(user_agent);
+        values
+            .iter()
+            .filter_map(|value| match value.split_once(':') {
+                Some((name, rest)) => {
+                    let name = name.trim().to_lowercase();
+                    if VALUE_DIRECTIVES.contains(&name.as_str()) || name.contains(',') {
+                        Some(value.as_str())
+                    } else if name == token {
+                        Some(rest)
+                    } else {
+                        None
+                    }
+                }
+                None => Some(value.as_str()),
+            })
+            .fold(Self::default(), |directives, value| directives.add(value))
+    }
+
+    /// Both sets of directives. A directive given in either applies.
+    pub(crate) fn merge(self, other: Self) -> Self {
+        RobotsDirectives {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+
+    // Adds a comma separated list of directives.
+    fn add(self, directives: &str) -> Self {
+        let mut new = self;
+        for directive in directives.split(',') {
+            match directive.trim().to_lowercase().as_str() {
+                "noindex" => new.noindex = true,
+                "nofollow" => new.nofollow = true,
+                "none" => {
+                    new.noindex = true;
+                    new.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+        new
+    }
+}
+
+/// The product token of a user agent, for example `simple_crawler` for
+/// `simple_crawler/0.1 (+https://test.com)`, in lower case. Robots.txt groups and directives are
+/// matched against it.
+pub(crate) fn product_token(user_agent: &str) -> String {
+    user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RobotsDirectives;
+    use select::document::Document;
+
+    #[test]
+    fn robots_directives_test() {
+        let ua = "simple_crawler/0.1";
+        let document = Document::from(
+            "<head>\
+                <meta name=\"ROBOTS\" content=\"NoIndex, follow\">\
+                <meta name=\"googlebot\" content=\"nofollow\">\
+            </head>",
+        );
+        let directives = RobotsDirectives::from_document(&document, ua);
+        assert!(directives.noindex());
+        assert!(!directives.nofollow());
+
+        let document = Document::from("<meta name=\"simple_crawler\" content=\"none\">");
+        let directives = RobotsDirectives::from_document(&document, ua);
+        assert!(directives.noindex() && directives.nofollow());
+
+        let headers = |values: &[&str]| {
+            let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            RobotsDirectives::from_headers(&values, ua)
+        };
+        assert!(headers(&["nofollow"]).nofollow());
+        assert!(headers(&["unavailable_after: 25 Jun 2010 15:00:00 PST, noindex"]).noindex());
+        assert!(headers(&["simple_crawler: noindex"]).noindex());
+        assert_eq!(
+            headers(&["googlebot: noindex, nofollow"]),
+            RobotsDirectives::default()
+        );
+        assert_eq!(
+            headers(&["noindex"]).merge(headers(&["nofollow"])),
+            headers(&["none"])
+        );
+    }
+}
@@ -0,0 +1,420 @@
+use std::error::Error as StdError;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Everything that can go wrong setting up or running a crawl. Errors for a single url are stored
+/// on its `Page` rather than ending the crawl. Sources are reference counted so pages holding an
+/// error can still be cloned. With the `serde` feature errors are serialized as their kind, url
+/// and message. Errors a page can fail with are read back as the same kind with the message of
+/// their source kept in place of the source, anything else becomes a `Restored` error.
+#[derive(Clone, Debug, Error)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "ErrorRecord", from = "ErrorRecord")
+)]
+pub enum CrawlError {
+    /// The url given to `SimpleCrawler::url` couldn't be parsed.
+    #[error("invalid base url {url}")]
+    InvalidBaseUrl {
+        url: String,
+        #[source]
+        source: url::ParseError,
+    },
+    /// An include or exclude pattern given to `ScopePolicy` isn't a valid regex.
+    #[error("invalid scope pattern {pattern}")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+    /// The HTTP client couldn't be built.
+    #[error("failed to build HTTP client")]
+    Client {
+        #[source]
+        source: Arc<reqwest::Error>,
+    },
+    /// The host name of the url couldn't be resolved.
+    #[error("failed to resolve host for {url}")]
+    Dns {
+        url: String,
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// The connection to the host couldn't be made.
+    #[error("failed to connect for {url}")]
+    Connect {
+        url: String,
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// The request timed out.
+    #[error("request timed out for {url}")]
+    Timeout {
+        url: String,
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// The server kept responding with a 5xx or 429 status.
+    #[error("server responded with {status} for {url}")]
+    Status { url: String, status: u16 },
+    /// The url's redirects led back to a url already in the chain.
+    #[error("redirect loop for {url}")]
+    RedirectLoop { url: String },
+    /// The url redirected more times than `RedirectPolicy::max_hops`.
+    #[error("more than {max_hops} redirects for {url}")]
+    TooManyRedirects { url: String, max_hops: u32 },
+    /// The response body couldn't be read or decoded.
+    #[error("failed to read body for {url}")]
+    Body {
+        url: String,
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// The response body was larger than `max_body_size` so it wasn't read.
+    #[error("body for {url} is larger than {max_body_size} bytes")]
+    BodyTooLarge { url: String, max_body_size: u64 },
+    /// Any other failure sending the request.
+    #[error("request failed for {url}")]
+    Request {
+        url: String,
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// A failure read back from a checkpoint or serialized crawl which isn't a kind of error a
+    /// page can fail with. Only the message of the original error is kept.
+    #[error("{message}")]
+    Restored { url: String, message: String },
+    /// The checkpoint couldn't be opened, read or written.
+    #[error("failed to checkpoint crawl to {}", path.display())]
+    Checkpoint {
+        path: PathBuf,
+        #[source]
+        source: Arc<rusqlite::Error>,
+    },
+    /// The crawl's `CrawlStore` couldn't be read or written. The crawl stops as it can't carry on
+    /// without its pages.
+    #[error("failed to read or write the crawl store")]
+    Store {
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// An export couldn't be written.
+    #[error("failed to write export")]
+    Export {
+        #[source]
+        source: Arc<std::io::Error>,
+    },
+    /// A body kept on disk by `BodyRetention::Disk` couldn't be written or read back. The crawl
+    /// stops as it can't keep what it was asked to.
+    #[error("failed to store body at {}", path.display())]
+    BodyStore {
+        path: PathBuf,
+        #[source]
+        source: Arc<std::io::Error>,
+    },
+    /// A WARC file couldn't be written. The crawl stops as it can't archive what it was asked to.
+    #[error("failed to write WARC file {}", path.display())]
+    Warc {
+        path: PathBuf,
+        #[source]
+        source: Arc<std::io::Error>,
+    },
+    /// The task fetching the url panicked or was cancelled.
+    #[error("request task failed for {url}")]
+    Task {
+        url: String,
+        #[source]
+        source: Arc<tokio::task::JoinError>,
+    },
+}
+
+impl CrawlError {
+    /// Sorts a reqwest error for a url into the matching variant.
+    pub(crate) fn from_reqwest(url: &str, e: reqwest::Error) -> Self {
+        let url = url.to_owned();
+        if e.is_timeout() {
+            CrawlError::Timeout {
+                url,
+                source: Arc::new(e),
+            }
+        } else if e.is_connect() && is_dns(&e) {
+            CrawlError::Dns {
+                url,
+                source: Arc::new(e),
+            }
+        } else if e.is_connect() {
+            CrawlError::Connect {
+                url,
+                source: Arc::new(e),
+            }
+        } else if e.is_body() || e.is_decode() {
+            CrawlError::Body {
+                url,
+                source: Arc::new(e),
+            }
+        } else {
+            CrawlError::Request {
+                url,
+                source: Arc::new(e),
+            }
+        }
+    }
+
+    /// The url the error happened for.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            CrawlError::InvalidBaseUrl { url, .. }
+            | CrawlError::Dns { url, .. }
+            | CrawlError::Connect { url, .. }
+            | CrawlError::Timeout { url, .. }
+            | CrawlError::Status { url, .. }
+            | CrawlError::RedirectLoop { url }
+            | CrawlError::TooManyRedirects { url, .. }
+            | CrawlError::Body { url, .. }
+            | CrawlError::BodyTooLarge { url, .. }
+            | CrawlError::Request { url, .. }
+            | CrawlError::Restored { url, .. }
+            | CrawlError::Task { url, .. } => Some(url),
+            CrawlError::Client { .. }
+            | CrawlError::InvalidPattern { .. }
+            | CrawlError::Checkpoint { .. }
+            | CrawlError::Store { .. }
+            | CrawlError::Export { .. }
+            | CrawlError::BodyStore { .. }
+            | CrawlError::Warc { .. } => None,
+        }
+    }
+
+    /// The name of the kind of error, for example `status` or `timeout`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CrawlError::InvalidBaseUrl { .. } => "invalid_base_url",
+            CrawlError::InvalidPattern { .. } => "invalid_pattern",
+            CrawlError::Client { .. } => "client",
+            CrawlError::Dns { .. } => "dns",
+            CrawlError::Connect { .. } => "connect",
+            CrawlError::Timeout { .. } => "timeout",
+            CrawlError::Status { .. } => "status",
+            CrawlError::RedirectLoop { .. } => "redirect_loop",
+            CrawlError::TooManyRedirects { .. } => "too_many_redirects",
+            CrawlError::Body { .. } => "body",
+            CrawlError::BodyTooLarge { .. } => "body_too_large",
+            CrawlError::Request { .. } => "request",
+            CrawlError::Restored { .. } => "restored",
+            CrawlError::Checkpoint { .. } => "checkpoint",
+            CrawlError::Store { .. } => "store",
+            CrawlError::Export { .. } => "export",
+            CrawlError::BodyStore { .. } => "body_store",
+            CrawlError::Warc { .. } => "warc",
+            CrawlError::Task { .. } => "task",
+        }
+    }
+
+    /// What's needed besides the kind and url to read a page's error back as it was, or `None`
+    /// if it isn't a kind of error a page can fail with. Sources are kept as their message.
+    pub(crate) fn detail(&self) -> Option<String> {
+        match self {
+            CrawlError::Status { status, .. } => Some(status.to_string()),
+            CrawlError::BodyTooLarge { max_body_size, .. } => Some(max_body_size.to_string()),
+            CrawlError::TooManyRedirects { max_hops, .. } => Some(max_hops.to_string()),
+            CrawlError::RedirectLoop { .. } => Some(String::new()),
+            CrawlError::Dns { source, .. }
+            | CrawlError::Connect { source, .. }
+            | CrawlError::Timeout { source, .. }
+            | CrawlError::Request { source, .. }
+            | CrawlError::Body { source, .. } => Some(source.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Rebuilds a page's error from its kind, url and `detail`. Returns `None` if the kind isn't
+    /// one a page can fail with or the detail doesn't match it.
+    pub(crate) fn restore(kind: &str, url: &str, detail: &str) -> Option<Self> {
+        let url = url.to_owned();
+        let source =
+            || -> Arc<dyn StdError + Send + Sync> { Arc::new(RestoredSource(detail.to_owned())) };
+        Some(match kind {
+            "status" => CrawlError::Status {
+                url,
+                status: detail.parse().ok()?,
+            },
+            "body_too_large" => CrawlError::BodyTooLarge {
+                url,
+                max_body_size: detail.parse().ok()?,
+            },
+            "too_many_redirects" => CrawlError::TooManyRedirects {
+                url,
+                max_hops: detail.parse().ok()?,
+            },
+            "redirect_loop" => CrawlError::RedirectLoop { url },
+            "dns" => CrawlError::Dns {
+                url,
+                source: source(),
+            },
+            "connect" => CrawlError::Connect {
+                url,
+                source: source(),
+            },
+            "timeout" => CrawlError::Timeout {
+                url,
+                source: source(),
+            },
+            "request" => CrawlError::Request {
+                url,
+                source: source(),
+            },
+            "body" => CrawlError::Body {
+                url,
+                source: source(),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Whether another attempt at the request could succeed.
+    pub(crate) fn is_retryable(&self) -> bool {
+        match self {
+            CrawlError::Connect { .. } | CrawlError::Timeout { .. } => true,
+            CrawlError::Status { status, .. } => is_retryable_status(*status),
+            _ => false,
+        }
+    }
+}
+
+// Errors hold sources which can't be compared so two errors are equal if they're the same kind of
+// error for the same url with the same message.
+impl PartialEq for CrawlError {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.url() == other.url()
+            && self.to_string() == other.to_string()
+    }
+}
+
+// The source of an error read back from a checkpoint or serialized crawl. Only its message is
+// kept.
+#[derive(Debug, Error)]
+#[error("{0}")]
+struct RestoredSource(String);
+
+// How an error is serialized.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ErrorRecord {
+    kind: String,
+    url: Option<String>,
+    message: String,
+    status: Option<u16>,
+    max_body_size: Option<u64>,
+    #[serde(default)]
+    detail: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl From<CrawlError> for ErrorRecord {
+    fn from(e: CrawlError) -> Self {
+        ErrorRecord {
+            kind: e.kind().to_owned(),
+            url: e.url().map(|url| url.to_owned()),
+            message: e.to_string(),
+            status: match e {
+                CrawlError::Status { status, .. } => Some(status),
+                _ => None,
+            },
+            max_body_size: match e {
+                CrawlError::BodyTooLarge { max_body_size, .. } => Some(max_body_size),
+                _ => None,
+            },
+            detail: e.detail(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<ErrorRecord> for CrawlError {
+    fn from(record: ErrorRecord) -> Self {
+        let url = record.url.unwrap_or_default();
+        let kind = &record.kind;
+        let restored = record
+            .detail
+            .as_deref()
+            .and_then(|detail| CrawlError::restore(kind, &url, detail));
+        if let Some(e) = restored {
+            return e;
+        }
+        // Records written before `detail` was added only had these.
+        match (record.kind.as_str(), record.status, record.max_body_size) {
+            ("status", Some(status), _) => CrawlError::Status { url, status },
+            ("body_too_large", _, Some(max_body_size)) => {
+                CrawlError::BodyTooLarge { url, max_body_size }
+            }
+            _ => CrawlError::Restored {
+                url,
+                message: record.message,
+            },
+        }
+    }
+}
+
+pub(crate) fn is_retryable_status(status: u16) -> bool {
+    status == 429 || (500..=599).contains(&status)
+}
+
+// reqwest doesn't separate resolver failures from other connection failures so look for the
+// resolver's error in the source chain.
+fn is_dns(e: &reqwest::Error) -> bool {
+    let mut source = e.source();
+    while let Some(s) = source {
+        if s.to_string().contains("dns error") {
+            return true;
+        }
+        source = s.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrawlError;
+
+    #[test]
+    fn retryable_test() {
+        let status = |status| CrawlError::Status {
+            url: "https://test.com".to_owned(),
+            status,
+        };
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert_eq!(status(503), status(503));
+        assert_ne!(status(503), status(502));
+        assert_eq!(status(503).url(), Some("https://test.com"));
+    }
+
+    #[test]
+    fn restore_test() {
+        let url = "https://test.com";
+        let timeout = CrawlError::restore("timeout", url, "operation timed out").unwrap();
+        assert!(matches!(timeout, CrawlError::Timeout { .. }));
+        assert!(timeout.is_retryable());
+        assert_eq!(
+            timeout.to_string(),
+            "request timed out for https://test.com"
+        );
+        assert_eq!(timeout.detail().as_deref(), Some("operation timed out"));
+
+        let too_many = CrawlError::TooManyRedirects {
+            url: url.to_owned(),
+            max_hops: 5,
+        };
+        let detail = too_many.detail().unwrap();
+        assert_eq!(
+            CrawlError::restore(too_many.kind(), url, &detail),
+            Some(too_many)
+        );
+        assert_eq!(CrawlError::restore("status", url, "x"), None);
+        assert_eq!(CrawlError::restore("task", url, ""), None);
+    }
+}
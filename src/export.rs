@@ -0,0 +1,550 @@
+use super::checkpoint::{change_freq_name, link_kind_name, skip_reason};
+use super::store::CrawlStore;
+use super::{CrawlError, CrawlEvent, Page, PageState, SimpleCrawler};
+use quick_xml::escape::escape;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::Write;
+use std::sync::Arc;
+
+const CSV_COLUMNS: &[&str] = &[
+    "url",
+    "state",
+    "reason",
+    "status",
+    "depth",
+    "attempts",
+    "final_url",
+    "redirects",
+    "content_type",
+    "content_length",
+    "latency_ms",
+    "fetched_at",
+    "canonical_url",
+    "noindex",
+    "nofollow",
+    "referrers",
+    "discovered_by",
+    "link_kind",
+    "sitemap",
+    "title",
+    "description",
+    "h1",
+];
+
+// The version of the JSON Lines schema written in each record. It's bumped whenever a field is
+// renamed, removed or changes meaning.
+const JSON_LINES_VERSION: u32 = 1;
+
+const GRAPHML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="state" for="node" attr.name="state" attr.type="string"/>
+  <key id="status" for="node" attr.name="status" attr.type="int"/>
+  <key id="depth" for="node" attr.name="depth" attr.type="int"/>
+  <graph id="crawl" edgedefault="directed">
+"#;
+
+/// The formats a crawl can be exported in with an `Exporter`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// One JSON object per line for each page holding everything recorded about it. This is a
+    /// flat schema of its own rather than the serde representation of a page and each record
+    /// says which version of it was written.
+    JsonLines,
+    /// A header row then one row per page with its main metadata, for spreadsheets.
+    Csv,
+    /// The link graph as GraphML with a node for each page and an edge for each link.
+    GraphMl,
+    /// The link graph as a Graphviz DOT digraph.
+    Dot,
+}
+
+/// The Exporter struct writes pages and the links between them in an `ExportFormat`. It's used by
+/// `SimpleCrawler::export` for a finished crawl and can be fed the events of
+/// `SimpleCrawler::crawl_stream` to export a crawl as it happens. Each page is written as soon as
+/// it's given so the writer should be buffered.
+///
+/// A streamed page is written when it's fetched so links to it found afterwards aren't among its
+/// referrers, and only the link each url was discovered through is an edge of the graph.
+#[derive(Debug)]
+pub struct Exporter<W: Write> {
+    format: ExportFormat,
+    writer: W,
+    started: bool,
+    // Graph nodes which have been written and the ends of edges which haven't been yet, so every
+    // edge ends at a node once the export is finished.
+    nodes: HashSet<String>,
+    missing: HashSet<String>,
+}
+
+impl<W: Write> Exporter<W> {
+    /// Create an Exporter writing to `writer` for example
+    /// `let exporter = Exporter::new(ExportFormat::JsonLines, BufWriter::new(file))`
+    pub fn new(format: ExportFormat, writer: W) -> Self {
+        Exporter {
+            format,
+            writer,
+            started: false,
+            nodes: HashSet::new(),
+            missing: HashSet::new(),
+        }
+    }
+
+    /// Writes a page. Graph formats write it as a node.
+    pub fn page(&mut self, url: &str, page: &Page) -> Result<(), CrawlError> {
+        let record = match self.format {
+            ExportFormat::JsonLines => format!("{}\n", json_record(url, page)),
+            ExportFormat::Csv => csv_record(url, page),
+            ExportFormat::GraphMl => format!(
+                "    <node id={}><data key=\"state\">{}</data>{}\
+                <data key=\"depth\">{}</data></node>\n",
+                xml_attr(url),
+                state_name(&page.state),
+                page.status()
+                    .map(|s| format!("<data key=\"status\">{}</data>", s))
+                    .unwrap_or_default(),
+                page.depth,
+            ),
+            ExportFormat::Dot => format!(
+                "  {} [state={}{}, depth={}];\n",
+                dot_id(url),
+                state_name(&page.state),
+                page.status()
+                    .map(|s| format!(", status={}", s))
+                    .unwrap_or_default(),
+                page.depth,
+            ),
+        };
+        self.missing.remove(url);
+        if self.is_graph() {
+            self.nodes.insert(url.to_owned());
+        }
+        self.write(&record)
+    }
+
+    /// Writes a link from `source` to `target` as an edge. Only graph formats write links.
+    pub fn link(&mut self, source: &str, target: &str) -> Result<(), CrawlError> {
+        let edge = match self.format {
+            ExportFormat::GraphMl => format!(
+                "    <edge source={} target={}/>\n",
+                xml_attr(source),
+                xml_attr(target)
+            ),
+            ExportFormat::Dot => format!("  {} -> {};\n", dot_id(source), dot_id(target)),
+            ExportFormat::JsonLines | ExportFormat::Csv => return Ok(()),
+        };
+        for url in &[source, target] {
+            if !self.nodes.contains(*url) {
+                self.missing.insert(url.to_string());
+            }
+        }
+        self.write(&edge)
+    }
+
+    /// Writes what a `CrawlEvent` from `SimpleCrawler::crawl_stream` says about the crawl. Fetched
+    /// and failed pages are written as pages and discovered or skipped urls as links.
+    pub fn event(&mut self, event: &CrawlEvent) -> Result<(), CrawlError> {
+        match event {
+            CrawlEvent::PageFetched { url, page } | CrawlEvent::PageFailed { url, page } => {
+                self.page(url, page)
+            }
+            CrawlEvent::LinkDiscovered { url, link, .. }
+            | CrawlEvent::LinkSkipped { url, link, .. } => self.link(&link.source, url),
+            CrawlEvent::CrawlFinished { .. } | CrawlEvent::CrawlFailed { .. } => Ok(()),
+        }
+    }
+
+    /// Writes anything the format needs at the end and returns the writer after flushing it.
+    /// Urls which are only the end of an edge are added to the graph as nodes without data.
+    pub fn finish(self) -> Result<W, CrawlError> {
+        let mut new = self;
+        let mut missing = new.missing.drain().collect::<Vec<_>>();
+        missing.sort();
+        for url in missing {
+            let node = match new.format {
+                ExportFormat::GraphMl => format!("    <node id={}/>\n", xml_attr(&url)),
+                _ => format!("  {};\n", dot_id(&url)),
+            };
+            new.write(&node)?;
+        }
+        let footer = match new.format {
+            ExportFormat::GraphMl => "  </graph>\n</graphml>\n",
+            ExportFormat::Dot => "}\n",
+            ExportFormat::JsonLines | ExportFormat::Csv => "",
+        };
+        new.write(footer)?;
+        new.writer.flush().map_err(export_error)?;
+        Ok(new.writer)
+    }
+
+    fn is_graph(&self) -> bool {
+        matches!(self.format, ExportFormat::GraphMl | ExportFormat::Dot)
+    }
+
+    // Writes the header before anything else.
+    fn write(&mut self, s: &str) -> Result<(), CrawlError> {
+        if !self.started {
+            self.started = true;
+            let header = match self.format {
+                ExportFormat::JsonLines => "".to_owned(),
+                ExportFormat::Csv => format!("{}\r\n", CSV_COLUMNS.join(",")),
+                ExportFormat::GraphMl => GRAPHML_HEADER.to_owned(),
+                ExportFormat::Dot => "digraph crawl {\n".to_owned(),
+            };
+            self.writer
+                .write_all(header.as_bytes())
+                .map_err(export_error)?;
+        }
+        self.writer.write_all(s.as_bytes()).map_err(export_error)
+    }
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// Writes every page of the crawl and the links between them in a format for example
+    /// `simple_crawler.export(ExportFormat::Csv, BufWriter::new(file))?`. Pages are written in the
+    /// order the store gives them and each is followed by the links to it from its referrers. The
+    /// writer is returned once everything has been written.
+    pub fn export<W: Write>(&self, format: ExportFormat, writer: W) -> Result<W, CrawlError> {
+        let mut exporter = Exporter::new(format, writer);
+        for page in self.urls.iter() {
+            let (url, page) = page?;
+            exporter.page(&url, &page)?;
+            for referrer in page.referrers.iter() {
+                exporter.link(referrer, &url)?;
+            }
+        }
+        exporter.finish()
+    }
+}
+
+fn export_error(e: std::io::Error) -> CrawlError {
+    CrawlError::Export {
+        source: Arc::new(e),
+    }
+}
+
+fn state_name(state: &PageState) -> &'static str {
+    match state {
+        PageState::Pending => "pending",
+        PageState::Fetched => "fetched",
+        PageState::Failed(_) => "failed",
+        PageState::Skipped(_) => "skipped",
+    }
+}
+
+// Why a page failed or was skipped.
+fn state_reason(state: &PageState) -> Option<String> {
+    match state {
+        PageState::Failed(e) => Some(e.to_string()),
+        PageState::Skipped(reason) => Some(skip_reason(reason)),
+        PageState::Pending | PageState::Fetched => None,
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_or_null<T>(value: Option<T>, f: impl FnOnce(T) -> String) -> String {
+    value.map(f).unwrap_or_else(|| "null".to_owned())
+}
+
+fn json_number<T: Display>(value: Option<T>) -> String {
+    json_or_null(value, |v| v.to_string())
+}
+
+fn json_array<'a>(values: impl Iterator<Item = &'a String>) -> String {
+    let values = values.map(|v| json_string(v)).collect::<Vec<_>>();
+    format!("[{}]", values.join(","))
+}
+
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}", json_string(name), value))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_record(url: &str, page: &Page) -> String {
+    let response = page.response.as_ref();
+    json_object(&[
+        ("version", JSON_LINES_VERSION.to_string()),
+        ("url", json_string(url)),
+        ("state", json_string(state_name(&page.state))),
+        (
+            "reason",
+            json_or_null(state_reason(&page.state), |r| json_string(&r)),
+        ),
+        ("depth", page.depth.to_string()),
+        ("attempts", page.attempts.to_string()),
+        ("status", json_number(page.status())),
+        ("final_url", json_or_null(page.final_url(), json_string)),
+        (
+            "redirects",
+            format!(
+                "[{}]",
+                page.redirects()
+                    .iter()
+                    .map(|hop| json_object(&[
+                        ("url", json_string(&hop.url)),
+                        ("status", hop.status.to_string()),
+                        ("location", json_string(&hop.location)),
+                    ]))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        ),
+        (
+            "headers",
+            json_or_null(response, |r| {
+                let headers = r
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), json_string(value)))
+                    .collect::<Vec<_>>();
+                json_object(&headers)
+            }),
+        ),
+        (
+            "content_type",
+            json_or_null(page.content_type(), json_string),
+        ),
+        ("content_length", json_number(page.content_length())),
+        (
+            "latency_ms",
+            json_number(page.latency().map(|l| l.as_secs_f64() * 1000.0)),
+        ),
+        (
+            "fetched_at",
+            json_or_null(page.fetched_at(), |t| {
+                json_string(&httpdate::fmt_http_date(t))
+            }),
+        ),
+        (
+            "canonical_url",
+            json_or_null(page.canonical_url(), json_string),
+        ),
+        (
+            "robots_directives",
+            json_object(&[
+                ("noindex", page.robots_directives.noindex.to_string()),
+                ("nofollow", page.robots_directives.nofollow.to_string()),
+            ]),
+        ),
+        ("referrers", json_array(page.referrers.iter())),
+        (
+            "discovered_by",
+            json_or_null(page.discovered_by.as_ref(), |link| {
+                json_object(&[
+                    ("source", json_string(&link.source)),
+                    ("text", json_string(&link.text)),
+                    ("rel", json_array(link.rel.iter())),
+                    ("kind", json_string(link_kind_name(link.kind))),
+                ])
+            }),
+        ),
+        (
+            "sitemap",
+            json_or_null(page.sitemap.as_ref(), |entry| {
+                json_object(&[
+                    ("sitemap", json_string(&entry.sitemap)),
+                    ("lastmod", json_or_null(entry.lastmod(), json_string)),
+                    (
+                        "changefreq",
+                        json_or_null(entry.changefreq, |c| json_string(change_freq_name(c))),
+                    ),
+                    ("priority", json_number(entry.priority)),
+                ])
+            }),
+        ),
+        (
+            "metadata",
+            json_or_null(page.metadata.as_ref(), |metadata| {
+                let pairs = |pairs: &[(String, String)]| {
+                    let pairs = pairs
+                        .iter()
+                        .map(|(name, value)| json_array([name, value].iter().copied()))
+                        .collect::<Vec<_>>();
+                    format!("[{}]", pairs.join(","))
+                };
+                let headings = metadata
+                    .headings
+                    .iter()
+                    .map(|(level, text)| {
+                        json_object(&[("level", level.to_string()), ("text", json_string(text))])
+                    })
+                    .collect::<Vec<_>>();
+                json_object(&[
+                    ("title", json_or_null(metadata.title(), json_string)),
+                    (
+                        "description",
+                        json_or_null(metadata.description(), json_string),
+                    ),
+                    ("robots", json_or_null(metadata.robots(), json_string)),
+                    ("headings", format!("[{}]", headings.join(","))),
+                    ("canonical", json_or_null(metadata.canonical(), json_string)),
+                    ("hreflang", pairs(&metadata.hreflang)),
+                    ("open_graph", pairs(&metadata.open_graph)),
+                    ("twitter", pairs(&metadata.twitter)),
+                    ("json_ld", json_array(metadata.json_ld.iter())),
+                ])
+            }),
+        ),
+        (
+            "data",
+            json_object(
+                &page
+                    .data
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), json_string(value)))
+                    .collect::<Vec<_>>(),
+            ),
+        ),
+    ])
+}
+
+// Fields holding a comma, quote or line break are quoted with quotes doubled.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn csv_record(url: &str, page: &Page) -> String {
+    let text = |value: Option<&str>| value.unwrap_or_default().to_owned();
+    let number = |value: Option<String>| value.unwrap_or_default();
+    let metadata = page.metadata.as_ref();
+    let response = page.response.as_ref();
+    let fields = [
+        url.to_owned(),
+        state_name(&page.state).to_owned(),
+        state_reason(&page.state).unwrap_or_default(),
+        number(page.status().map(|s| s.to_string())),
+        page.depth.to_string(),
+        page.attempts.to_string(),
+        text(page.final_url()),
+        number(response.map(|r| r.redirects.len().to_string())),
+        text(page.content_type()),
+        number(page.content_length().map(|l| l.to_string())),
+        number(
+            page.latency()
+                .map(|l| (l.as_secs_f64() * 1000.0).to_string()),
+        ),
+        number(page.fetched_at().map(httpdate::fmt_http_date)),
+        text(page.canonical_url()),
+        page.robots_directives.noindex.to_string(),
+        page.robots_directives.nofollow.to_string(),
+        page.referrers.len().to_string(),
+        text(page.discovered_by.as_ref().map(|l| l.source.as_str())),
+        text(page.discovered_by.as_ref().map(|l| link_kind_name(l.kind))),
+        text(page.sitemap.as_ref().map(|s| s.sitemap.as_str())),
+        text(metadata.and_then(|m| m.title())),
+        text(metadata.and_then(|m| m.description())),
+        text(metadata.and_then(|m| m.headings_at(1).next())),
+    ];
+    let fields = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>();
+    format!("{}\r\n", fields.join(","))
+}
+
+fn xml_attr(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+fn dot_id(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_field, json_string, ExportFormat, Exporter};
+    use crate::{CrawlError, Link, LinkKind, Page, PageState, SkipReason};
+
+    fn export(format: ExportFormat) -> Result<String, CrawlError> {
+        let page = Page {
+            state: PageState::Skipped(SkipReason::NoFollow),
+            depth: 1,
+            referrers: vec!["https://test.com/".to_owned()],
+            discovered_by: Some(Link::new(
+                "https://test.com/",
+                "a, \"b\"",
+                Some("nofollow"),
+                LinkKind::Anchor,
+            )),
+            data: vec![("price".to_owned(), "9.99".to_owned())]
+                .into_iter()
+                .collect(),
+            ..Page::new()
+        };
+        let mut exporter = Exporter::new(format, vec![]);
+        exporter.page("https://test.com/a?x=1&y=2", &page)?;
+        exporter.link("https://test.com/", "https://test.com/a?x=1&y=2")?;
+        let bytes = exporter.finish()?;
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    #[test]
+    fn export_test() -> Result<(), CrawlError> {
+        assert_eq!(json_string("a\"\\\n\u{1}"), r#""a\"\\\n\u0001""#);
+        assert_eq!(csv_field("a, \"b\""), r#""a, ""b""""#);
+
+        let json = export(ExportFormat::JsonLines)?;
+        assert_eq!(json.lines().count(), 1);
+        assert!(json.starts_with(concat!(
+            r#"{"version":1,"url":"https://test.com/a?x=1&y=2","state":"skipped","#,
+            r#""reason":"nofollow","#,
+            r#""depth":1,"attempts":0,"status":null,"final_url":null,"redirects":[],"#,
+        )));
+        assert!(json.contains(concat!(
+            r#""discovered_by":{"source":"https://test.com/","text":"a, \"b\"","#,
+            r#""rel":["nofollow"],"kind":"anchor"}"#,
+        )));
+        assert!(json.ends_with("\"metadata\":null,\"data\":{\"price\":\"9.99\"}}\n"));
+
+        let csv = export(ExportFormat::Csv)?;
+        let rows = csv.split("\r\n").collect::<Vec<_>>();
+        assert!(rows[0].starts_with("url,state,reason,status,depth,"));
+        assert_eq!(
+            rows[1],
+            "https://test.com/a?x=1&y=2,skipped,nofollow,,1,0,,,,,,,,false,false,1,\
+            https://test.com/,anchor,,,,"
+        );
+
+        let graphml = export(ExportFormat::GraphMl)?;
+        assert!(graphml.contains(concat!(
+            r#"<node id="https://test.com/a?x=1&amp;y=2"><data key="state">skipped</data>"#,
+            r#"<data key="depth">1</data></node>"#,
+        )));
+        assert!(graphml.contains(
+            r#"<edge source="https://test.com/" target="https://test.com/a?x=1&amp;y=2"/>"#
+        ));
+        // The referrer wasn't exported as a page but is still a node.
+        assert!(graphml.contains(r#"<node id="https://test.com/"/>"#));
+        assert!(graphml.ends_with("</graph>\n</graphml>\n"));
+
+        assert_eq!(
+            export(ExportFormat::Dot)?,
+            "digraph crawl {\n  \
+            \"https://test.com/a?x=1&y=2\" [state=skipped, depth=1];\n  \
+            \"https://test.com/\" -> \"https://test.com/a?x=1&y=2\";\n  \
+            \"https://test.com/\";\n}\n"
+        );
+        Ok(())
+    }
+}
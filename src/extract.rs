@@ -0,0 +1,201 @@
+use super::LinkKind;
+use regex::Regex;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Name};
+use std::sync::OnceLock;
+
+/// A link found on a page before it's resolved against the page's url.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FoundLink<'a> {
+    pub(crate) href: String,
+    pub(crate) text: String,
+    pub(crate) rel: Option<&'a str>,
+    pub(crate) kind: LinkKind,
+}
+
+impl<'a> FoundLink<'a> {
+    fn new(href: &str, node: &Node<'a>, kind: LinkKind) -> Self {
+        FoundLink {
+            href: href.trim().to_owned(),
+            text: node.text(),
+            rel: node.attr("rel"),
+            kind,
+        }
+    }
+}
+
+/// Finds every link of the given kinds on a page. Links to a fragment of the same page aren't
+/// links to another page so they're left out.
+pub(crate) fn find_links<'a>(document: &'a Document, kinds: &[LinkKind]) -> Vec<FoundLink<'a>> {
+    let mut links = vec![];
+    for kind in kinds {
+        match kind {
+            LinkKind::Anchor => links.extend(attrs(document, "a", "href", *kind)),
+            LinkKind::Area => links.extend(document.find(Name("area")).filter_map(|node| {
+                let href = node.attr("href")?;
+                Some(FoundLink {
+                    text: node.attr("alt").unwrap_or_default().to_owned(),
+                    ..FoundLink::new(href, &node, *kind)
+                })
+            })),
+            LinkKind::Link => links.extend(attrs(document, "link", "href", *kind)),
+            LinkKind::Frame => {
+                links.extend(attrs(document, "iframe", "src", *kind));
+                links.extend(attrs(document, "frame", "src", *kind));
+            }
+            LinkKind::Image => {
+                links.extend(attrs(document, "img", "src", *kind));
+                links.extend(srcsets(document, "img", *kind));
+            }
+            LinkKind::Script => links.extend(attrs(document, "script", "src", *kind)),
+            LinkKind::Source => {
+                links.extend(attrs(document, "source", "src", *kind));
+                links.extend(srcsets(document, "source", *kind));
+            }
+            LinkKind::Form => links.extend(
+                document
+                    .find(Name("form"))
+                    .filter(|node| {
+                        node.attr("method")
+                            .is_none_or(|m| m.trim().eq_ignore_ascii_case("get"))
+                    })
+                    .filter_map(|node| Some(FoundLink::new(node.attr("action")?, &node, *kind))),
+            ),
+            LinkKind::MetaRefresh => links.extend(
+                document
+                    .find(Name("meta"))
+                    .filter(|node| {
+                        node.attr("http-equiv")
+                            .is_some_and(|h| h.trim().eq_ignore_ascii_case("refresh"))
+                    })
+                    .filter_map(|node| {
+                        let href = refresh_url(node.attr("content")?)?;
+                        Some(FoundLink::new(href, &node, *kind))
+                    }),
+            ),
+            LinkKind::Css => {
+                let styles = document
+                    .find(Name("style"))
+                    .map(|node| (node.text(), node))
+                    .chain(
+                        document
+                            .find(Attr("style", ()))
+                            .filter_map(|node| Some((node.attr("style")?.to_owned(), node))),
+                    );
+                for (css, node) in styles {
+                    links.extend(css_urls(&css).map(|href| FoundLink {
+                        text: "".to_owned(),
+                        ..FoundLink::new(href, &node, *kind)
+                    }));
+                }
+            }
+            // These aren't on the page, they're asked for by the crawl's handler.
+            LinkKind::Handler => {}
+        }
+    }
+    links.retain(|link| !link.href.is_empty() && !link.href.starts_with('#'));
+    links
+}
+
+fn attrs<'a>(
+    document: &'a Document,
+    name: &'a str,
+    attr: &'a str,
+    kind: LinkKind,
+) -> impl Iterator<Item = FoundLink<'a>> + 'a {
+    document
+        .find(Name(name))
+        .filter_map(move |node| Some(FoundLink::new(node.attr(attr)?, &node, kind)))
+}
+
+// Each candidate in a srcset is a url followed by an optional width or density.
+fn srcsets<'a>(
+    document: &'a Document,
+    name: &'a str,
+    kind: LinkKind,
+) -> impl Iterator<Item = FoundLink<'a>> + 'a {
+    document.find(Name(name)).flat_map(move |node| {
+        node.attr("srcset")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|candidate| candidate.split_whitespace().next())
+            .map(|href| FoundLink::new(href, &node, kind))
+            .collect::<Vec<_>>()
+    })
+}
+
+// The content of a refresh is a delay optionally followed by `; url=...`.
+fn refresh_url(content: &str) -> Option<&str> {
+    let (_, rest) = content.split_once(';')?;
+    let rest = rest.trim();
+    let url = match rest.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url=") => &rest[4..],
+        _ => rest,
+    };
+    Some(url.trim().trim_matches(|c| c == '\'' || c == '"'))
+}
+
+fn css_urls(css: &str) -> impl Iterator<Item = &str> {
+    static CSS_URL: OnceLock<Regex> = OnceLock::new();
+    let regex = CSS_URL.get_or_init(|| {
+        Regex::new(r#"url\(\s*['"]?([^'")\s]+)['"]?\s*\)"#).expect("valid css url regex")
+    });
+    regex
+        .captures_iter(css)
+        .filter_map(|captures| captures.get(1))
+        .map(|url| url.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_links;
+    use crate::LinkKind;
+    use select::document::Document;
+
+    #[test]
+    fn find_links_test() {
+        let document = Document::from(
+            "<head>\
+                <meta http-equiv=\"Refresh\" content=\"5; URL='/refresh'\">\
+                <link rel=\"next\" href=\"/page2\">\
+                <style>body { background: url(\"/bg.png\") }</style>\
+            </head>\
+            <a href=\"#top\">top</a><a href=\"/a\">a</a>\
+            <map><area href=\"/area\" alt=\"Area\"></map>\
+            <iframe src=\"/frame\"></iframe>\
+            <img src=\"/img.png\" srcset=\"/img-2x.png 2x, /img-3x.png 3x\">\
+            <script src=\"/app.js\"></script>\
+            <picture><source srcset=\"/pic.webp\"></picture>\
+            <form action=\"/search\"></form><form method=\"post\" action=\"/login\"></form>\
+            <div style=\"background-image: url('/div.png')\"></div>",
+        );
+
+        let hrefs = |kind| {
+            find_links(&document, &[kind])
+                .into_iter()
+                .map(|link| link.href)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hrefs(LinkKind::Anchor), ["/a"]);
+        assert_eq!(hrefs(LinkKind::Area), ["/area"]);
+        assert_eq!(hrefs(LinkKind::Link), ["/page2"]);
+        assert_eq!(hrefs(LinkKind::Frame), ["/frame"]);
+        assert_eq!(
+            hrefs(LinkKind::Image),
+            ["/img.png", "/img-2x.png", "/img-3x.png"]
+        );
+        assert_eq!(hrefs(LinkKind::Script), ["/app.js"]);
+        assert_eq!(hrefs(LinkKind::Source), ["/pic.webp"]);
+        assert_eq!(hrefs(LinkKind::Form), ["/search"]);
+        assert_eq!(hrefs(LinkKind::MetaRefresh), ["/refresh"]);
+        assert_eq!(hrefs(LinkKind::Css), ["/bg.png", "/div.png"]);
+
+        let area = &find_links(&document, &[LinkKind::Area])[0];
+        assert_eq!(area.text, "Area");
+        assert_eq!(
+            find_links(&document, &[LinkKind::Link])[0].rel,
+            Some("next")
+        );
+    }
+}
@@ -0,0 +1,577 @@
+use super::error::is_retryable_status;
+use super::limits::Budget;
+use super::politeness::HostLimiter;
+use super::redirect::{self, Redirect, RedirectPolicy};
+use super::warc::Exchange;
+use super::{utils, CrawlError, CrawlerConfig, Page, PageState, RetryPolicy};
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, LOCATION, RETRY_AFTER};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Response, StatusCode, Url, Version};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Response headers recorded on each page unless changed with `CrawlerBuilder::record_headers`.
+pub const DEFAULT_RECORD_HEADERS: &[&str] = &[
+    "cache-control",
+    "content-encoding",
+    "content-language",
+    "etag",
+    "last-modified",
+    "server",
+    "x-robots-tag",
+];
+
+/// What was learnt about a url from the response it was fetched with.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct ResponseInfo {
+    pub(crate) status: u16,
+    pub(crate) final_url: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) content_length: Option<u64>,
+    pub(crate) latency: Duration,
+    pub(crate) fetched_at: SystemTime,
+    pub(crate) retry_after: Option<Duration>,
+    pub(crate) robots_tags: Vec<String>,
+    pub(crate) redirects: Vec<Redirect>,
+}
+
+impl ResponseInfo {
+    fn new(
+        status: StatusCode,
+        final_url: &Url,
+        headers: &HeaderMap,
+        record_headers: &[String],
+        fetched_at: SystemTime,
+    ) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned())
+        };
+
+        ResponseInfo {
+            status: status.as_u16(),
+            final_url: final_url.to_string(),
+            headers: record_headers
+                .iter()
+                .filter_map(|name| header(name.as_str()).map(|v| (name.to_owned(), v)))
+                .collect(),
+            content_type: header(CONTENT_TYPE.as_str()),
+            content_length: header(CONTENT_LENGTH.as_str()).and_then(|v| v.parse().ok()),
+            latency: Duration::default(),
+            fetched_at,
+            retry_after: header(RETRY_AFTER.as_str()).and_then(|v| parse_retry_after(&v)),
+            // Kept whichever headers are recorded as they decide which links are followed.
+            robots_tags: headers
+                .get_all("x-robots-tag")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .map(|v| v.to_owned())
+                .collect(),
+            // A redirect is a single hop until it's added to the chain it's part of.
+            redirects: location(status, final_url, headers)
+                .map(|location| Redirect {
+                    url: final_url.to_string(),
+                    status: status.as_u16(),
+                    location: location.to_string(),
+                })
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    // Called once the body has been read. The body length is used when the server didn't send a
+    // Content-Length header.
+    fn finish(self, body: &[u8], started: Instant) -> Self {
+        let mut new = self;
+        new.content_length = new.content_length.or(Some(body.len() as u64));
+        new.latency = started.elapsed();
+        new
+    }
+}
+
+/// Builds the client used for every request in a crawl. Redirects are followed by the crawl so
+/// the client doesn't follow them.
+pub(crate) fn client(config: &CrawlerConfig) -> Result<Client, CrawlError> {
+    Client::builder()
+        .user_agent(&config.user_agent)
+        .timeout(config.request_timeout)
+        .connect_timeout(config.request_timeout)
+        .redirect(Policy::none())
+        .build()
+        .map_err(|e| CrawlError::Client {
+            source: Arc::new(e),
+        })
+}
+
+/// Builds the blocking client used for every request in a blocking crawl.
+#[cfg(feature = "blocking")]
+pub(crate) fn client_blocking(
+    config: &CrawlerConfig,
+) -> Result<reqwest::blocking::Client, CrawlError> {
+    reqwest::blocking::Client::builder()
+        .user_agent(&config.user_agent)
+        .timeout(config.request_timeout)
+        .connect_timeout(config.request_timeout)
+        .redirect(Policy::none())
+        .build()
+        .map_err(|e| CrawlError::Client {
+            source: Arc::new(e),
+        })
+}
+
+// Where a response redirects to.
+pub(crate) fn location(status: StatusCode, url: &Url, headers: &HeaderMap) -> Option<Url> {
+    let location = headers.get(LOCATION)?.to_str().ok()?;
+    status
+        .is_redirection()
+        .then(|| url.join(location.trim()).ok())
+        .flatten()
+}
+
+/// GET a url which isn't a page of the crawl such as robots.txt, following up to ten redirects.
+pub(crate) async fn get(client: &Client, url: &str) -> reqwest::Result<Response> {
+    let mut resp = client.get(url).send().await?;
+    for _ in 0..10 {
+        match location(resp.status(), resp.url(), resp.headers()) {
+            Some(next) => resp = client.get(next).send().await?,
+            None => break,
+        }
+    }
+    Ok(resp)
+}
+
+/// GET a url which isn't a page of the crawl with a blocking client, following up to ten
+/// redirects.
+#[cfg(feature = "blocking")]
+pub(crate) fn get_blocking(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> reqwest::Result<reqwest::blocking::Response> {
+    let mut resp = client.get(url).send()?;
+    for _ in 0..10 {
+        match location(resp.status(), resp.url(), resp.headers()) {
+            Some(next) => resp = client.get(next).send()?,
+            None => break,
+        }
+    }
+    Ok(resp)
+}
+
+// Records the result of an attempt on the page. A 5xx or 429 response or a redirect chain which
+// looped or went on too long is a failure but what was learnt from the response is still kept.
+fn record_attempt(
+    page: Page,
+    url: &str,
+    result: Result<(String, ResponseInfo), CrawlError>,
+    attempts: u32,
+    redirects: &RedirectPolicy,
+) -> Page {
+    let redirect_error = match &result {
+        Ok((_, response)) => redirect::redirect_error(url, response, redirects),
+        Err(_) => None,
+    };
+    let (body, state, response) = match (result, redirect_error) {
+        (Ok((_, response)), Some(e)) => ("".to_owned(), PageState::Failed(e), Some(response)),
+        (Ok((_, response)), None) if is_retryable_status(response.status) => (
+            "".to_owned(),
+            PageState::Failed(CrawlError::Status {
+                url: url.to_owned(),
+                status: response.status,
+            }),
+            Some(response),
+        ),
+        (Ok((body, response)), None) => (body, PageState::Fetched, Some(response)),
+        (Err(e), _) => ("".to_owned(), PageState::Failed(e), None),
+    };
+    Page {
+        body,
+        state,
+        attempts,
+        response,
+        ..page
+    }
+}
+
+// Adds a response to the redirect chain of the attempt it's part of, returning the url to request
+// next if the chain goes on. A chain stops at a loop, at the policy's limit or when it leaves the
+// crawl's scope unless that's allowed.
+fn next_hop(
+    info: &mut ResponseInfo,
+    chain: &mut Vec<Redirect>,
+    base_url: &str,
+    config: &CrawlerConfig,
+) -> Option<String> {
+    let next = info.redirects.first().map(|hop| hop.location.to_owned());
+    chain.append(&mut info.redirects);
+    let follow = next.as_ref().is_some_and(|next| {
+        let in_scope = utils::normalise_url(base_url, next, next, config).is_some();
+        chain.len() <= config.redirects.hops() as usize
+            && !redirect::is_loop(chain)
+            && (in_scope || config.redirects.follows_off_scope())
+    });
+    match follow {
+        true => next,
+        false => {
+            info.redirects = std::mem::take(chain);
+            None
+        }
+    }
+}
+
+// Whether another attempt should be made after this one.
+fn should_retry(page: &Page, retry: &RetryPolicy) -> bool {
+    page.attempts < retry.attempts() && page.error().is_some_and(|e| e.is_retryable())
+}
+
+// Where an attempt is up to.
+enum Stage {
+    // Nothing has been requested yet.
+    New,
+    // Waiting at a redirect to this url. The redirect's response is kept in case it isn't
+    // followed.
+    Redirected(String, Box<(String, ResponseInfo)>),
+    // The page is in its final state.
+    Done,
+}
+
+/// The requests made for a page. An attempt stops at every redirect it would follow so the crawl
+/// can check the hop before it's requested, then it's either carried on with `fetch_page` or
+/// stopped with `stop_redirect`. A retry starts again from the page's own url.
+pub(crate) struct Attempt {
+    pub(crate) url: String,
+    pub(crate) page: Page,
+    pub(crate) exchanges: Vec<Exchange>,
+    attempts: u32,
+    chain: Vec<Redirect>,
+    stage: Stage,
+}
+
+impl Attempt {
+    pub(crate) fn new(url: &str, page: Page) -> Self {
+        Attempt {
+            url: url.to_owned(),
+            page,
+            exchanges: vec![],
+            attempts: 0,
+            chain: vec![],
+            stage: Stage::New,
+        }
+    }
+
+    /// The url of the redirect the attempt is waiting at.
+    pub(crate) fn redirect(&self) -> Option<&str> {
+        match &self.stage {
+            Stage::Redirected(next, _) => Some(next),
+            Stage::New | Stage::Done => None,
+        }
+    }
+
+    /// Doesn't follow the redirect the attempt is waiting at so the page keeps the redirect as
+    /// its response, the same as a redirect leaving the crawl's scope.
+    pub(crate) fn stop_redirect(&mut self, config: &CrawlerConfig) {
+        if let Stage::Redirected(_, response) = std::mem::replace(&mut self.stage, Stage::Done) {
+            let (body, mut info) = *response;
+            info.redirects = std::mem::take(&mut self.chain);
+            let page = std::mem::replace(&mut self.page, Page::new());
+            self.page = record_attempt(
+                page,
+                &self.url,
+                Ok((body, info)),
+                self.attempts,
+                &config.redirects,
+            );
+        }
+    }
+
+    // The url to request next. A new attempt takes a page from the budget first and nothing is
+    // requested if there isn't one left.
+    fn start(&mut self, budget: &Budget) -> Option<String> {
+        match std::mem::replace(&mut self.stage, Stage::Done) {
+            Stage::New if budget.start_page().is_ok() => {
+                self.attempts = 1;
+                Some(self.url.to_owned())
+            }
+            Stage::Redirected(next, _) => Some(next),
+            Stage::New | Stage::Done => None,
+        }
+    }
+
+    // Records the response to a request. Returns how long to wait before trying the page again
+    // if it failed and can be retried. A retry waits for its backoff so it's only made if that
+    // leaves time in the crawl.
+    fn record(
+        &mut self,
+        result: Result<(String, ResponseInfo), CrawlError>,
+        base_url: &str,
+        config: &CrawlerConfig,
+        budget: &Budget,
+    ) -> Option<Duration> {
+        let result = match result {
+            Ok((body, mut info)) => {
+                budget.add_bytes(body.len() as u64);
+                if let Some(next) = next_hop(&mut info, &mut self.chain, base_url, config) {
+                    self.stage = Stage::Redirected(next, Box::new((body, info)));
+                    return None;
+                }
+                Ok((body, info))
+            }
+            Err(e) => Err(e),
+        };
+        self.chain.clear();
+        let page = std::mem::replace(&mut self.page, Page::new());
+        self.page = record_attempt(page, &self.url, result, self.attempts, &config.redirects);
+
+        let delay = config.retry.delay(self.attempts);
+        if !should_retry(&self.page, &config.retry) || !budget.allows_retry(delay) {
+            return None;
+        }
+        self.attempts += 1;
+        Some(delay)
+    }
+}
+
+/// GET a page retrying on failure until it's either fetched or failed or the attempt is waiting
+/// at a redirect. The page is left unchanged if a crawl limit has been reached. Failures aren't
+/// retried once the time or byte limit would be passed. Every response is kept on the attempt to
+/// be archived if the crawl has an `Archive`.
+pub(crate) async fn fetch_page(
+    client: &Client,
+    attempt: Attempt,
+    base_url: &str,
+    config: &CrawlerConfig,
+    budget: &Budget,
+    hosts: &Arc<HostLimiter>,
+) -> Attempt {
+    let mut attempt = attempt;
+    let mut url = match attempt.start(budget) {
+        Some(url) => url,
+        None => return attempt,
+    };
+    loop {
+        // Each hop of a redirect chain waits for its own host.
+        let permit = hosts.acquire(&url).await;
+        let result = fetch(client, &url, config, budget, &mut attempt.exchanges).await;
+        hosts.record(&url, &result);
+        drop(permit);
+        match attempt.record(result, base_url, config, budget) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => return attempt,
+        }
+        url = attempt.url.to_owned();
+    }
+}
+
+/// GET a page with a blocking client like `fetch_page`.
+#[cfg(feature = "blocking")]
+pub(crate) fn fetch_page_blocking(
+    client: &reqwest::blocking::Client,
+    attempt: Attempt,
+    base_url: &str,
+    config: &CrawlerConfig,
+    budget: &Budget,
+    hosts: &Arc<HostLimiter>,
+) -> Attempt {
+    let mut attempt = attempt;
+    let mut url = match attempt.start(budget) {
+        Some(url) => url,
+        None => return attempt,
+    };
+    loop {
+        let permit = hosts.acquire_blocking(&url);
+        let result = fetch_blocking(client, &url, config, budget, &mut attempt.exchanges);
+        hosts.record(&url, &result);
+        drop(permit);
+        match attempt.record(result, base_url, config, budget) {
+            Some(delay) => std::thread::sleep(delay),
+            None => return attempt,
+        }
+        url = attempt.url.to_owned();
+    }
+}
+
+// Retry-After is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    match value.trim().parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value.trim())
+            .ok()
+            .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default()),
+    }
+}
+
+// Fails early if the Content-Length header says the body is too large.
+fn check_body_size(
+    url: &str,
+    info: &ResponseInfo,
+    max_body_size: Option<u64>,
+) -> Result<(), CrawlError> {
+    match (info.content_length, max_body_size) {
+        (Some(length), Some(max_body_size)) if length > max_body_size => {
+            Err(CrawlError::BodyTooLarge {
+                url: url.to_owned(),
+                max_body_size,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Decodes a body the way reqwest does, using the charset of the Content-Type header and falling
+// back to UTF-8.
+fn decode(bytes: &[u8], info: &ResponseInfo) -> String {
+    let encoding = info
+        .content_type
+        .as_ref()
+        .and_then(|content_type| {
+            content_type.split(';').skip(1).find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("charset")
+                    .then(|| value.trim().trim_matches('"'))
+            })
+        })
+        .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+// The request and the start of the response to archive if the crawl has an `Archive`.
+fn exchange(
+    url: &str,
+    config: &CrawlerConfig,
+    fetched_at: SystemTime,
+    version: Version,
+    status: StatusCode,
+    headers: &HeaderMap,
+    remote_addr: Option<SocketAddr>,
+) -> Option<Exchange> {
+    config.archive.as_ref()?;
+    let exchange = Exchange::new(url, &config.user_agent, fetched_at);
+    Some(exchange.respond(version, status, headers, remote_addr))
+}
+
+/// GET a url returning the body and what was learnt from the response. The request times out
+/// at the crawl's request timeout or when `max_duration` is reached. The response is added to
+/// `exchanges` if the crawl is archived.
+async fn fetch(
+    client: &Client,
+    url: &str,
+    config: &CrawlerConfig,
+    budget: &Budget,
+    exchanges: &mut Vec<Exchange>,
+) -> Result<(String, ResponseInfo), CrawlError> {
+    let fetched_at = SystemTime::now();
+    let started = Instant::now();
+
+    let error = |e| CrawlError::from_reqwest(url, e);
+
+    let request = client.get(url).timeout(budget.request_timeout());
+    let mut resp = request.send().await.map_err(error)?;
+    let info = ResponseInfo::new(
+        resp.status(),
+        resp.url(),
+        resp.headers(),
+        &config.record_headers,
+        fetched_at,
+    );
+    check_body_size(url, &info, config.max_body_size)?;
+    let exchange = exchange(
+        url,
+        config,
+        fetched_at,
+        resp.version(),
+        resp.status(),
+        resp.headers(),
+        resp.remote_addr(),
+    );
+
+    let bytes = match config.max_body_size {
+        // Read the body a chunk at a time so a large body without a Content-Length header is
+        // never held in full.
+        Some(max_body_size) => {
+            let mut bytes = Vec::new();
+            while let Some(chunk) = resp.chunk().await.map_err(error)? {
+                bytes.extend_from_slice(&chunk);
+                if bytes.len() as u64 > max_body_size {
+                    return Err(CrawlError::BodyTooLarge {
+                        url: url.to_owned(),
+                        max_body_size,
+                    });
+                }
+            }
+            bytes
+        }
+        None => resp.bytes().await.map_err(error)?.to_vec(),
+    };
+
+    exchanges.extend(exchange.map(|exchange| exchange.body(&bytes)));
+    let info = info.finish(&bytes, started);
+    Ok((decode(&bytes, &info), info))
+}
+
+/// GET a url with a blocking client like `fetch`.
+#[cfg(feature = "blocking")]
+fn fetch_blocking(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    config: &CrawlerConfig,
+    budget: &Budget,
+    exchanges: &mut Vec<Exchange>,
+) -> Result<(String, ResponseInfo), CrawlError> {
+    use std::io::Read;
+
+    let fetched_at = SystemTime::now();
+    let started = Instant::now();
+
+    let error = |e| CrawlError::from_reqwest(url, e);
+
+    let request = client.get(url).timeout(budget.request_timeout());
+    let resp = request.send().map_err(error)?;
+    let info = ResponseInfo::new(
+        resp.status(),
+        resp.url(),
+        resp.headers(),
+        &config.record_headers,
+        fetched_at,
+    );
+    check_body_size(url, &info, config.max_body_size)?;
+    let exchange = exchange(
+        url,
+        config,
+        fetched_at,
+        resp.version(),
+        resp.status(),
+        resp.headers(),
+        resp.remote_addr(),
+    );
+
+    let bytes = match config.max_body_size {
+        // Read at most one byte more than the limit to tell if the body is too large.
+        Some(max_body_size) => {
+            let mut bytes = Vec::new();
+            resp.take(max_body_size.saturating_add(1))
+                .read_to_end(&mut bytes)
+                .map_err(|e| CrawlError::Body {
+                    url: url.to_owned(),
+                    source: Arc::new(e),
+                })?;
+            if bytes.len() as u64 > max_body_size {
+                return Err(CrawlError::BodyTooLarge {
+                    url: url.to_owned(),
+                    max_body_size,
+                });
+            }
+            bytes
+        }
+        None => resp.bytes().map_err(error)?.to_vec(),
+    };
+
+    exchanges.extend(exchange.map(|exchange| exchange.body(&bytes)));
+    let info = info.finish(&bytes, started);
+    Ok((decode(&bytes, &info), info))
+}
@@ -0,0 +1,241 @@
+use super::checkpoint::Checkpoint;
+use super::fetch::{self, Attempt};
+use super::limits::Budget;
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::warc::WarcWriter;
+use super::{body, handler, CrawlError, CrawlEvent, CrawlerConfig, PageState, SimpleCrawler};
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use reqwest::Client;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+
+/// How a frontier crawl requests its urls.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Workers {
+    /// Up to this many requests are polled together on the crawl's own task.
+    Concurrent(usize),
+    /// Up to this many requests each run on their own tokio task.
+    Parallel(usize),
+}
+
+impl Workers {
+    fn size(self) -> usize {
+        match self {
+            Workers::Concurrent(n) | Workers::Parallel(n) => n.max(1),
+        }
+    }
+
+    fn fetch(
+        self,
+        client: &Client,
+        attempt: Attempt,
+        base_url: &str,
+        config: &Arc<CrawlerConfig>,
+        budget: &Arc<Budget>,
+        hosts: &Arc<HostLimiter>,
+    ) -> BoxFuture<'static, (Attempt, Vec<String>)> {
+        let (client, config, budget) = (client.clone(), config.clone(), budget.clone());
+        let base_url = base_url.to_owned();
+        let hosts = hosts.clone();
+        let url = attempt.url.to_owned();
+        let page = attempt.page.to_owned();
+        // The page handler runs with the request so slow handlers don't hold up the crawl. It
+        // waits until the page is done with any redirects.
+        let request = async move {
+            let mut attempt =
+                fetch::fetch_page(&client, attempt, &base_url, &config, &budget, &hosts).await;
+            let urls = match attempt.redirect() {
+                Some(_) => vec![],
+                None => handler::handle_page(&attempt.url, &mut attempt.page, &config).await,
+            };
+            (attempt, urls)
+        };
+
+        match self {
+            Workers::Concurrent(_) => request.boxed(),
+            Workers::Parallel(_) => {
+                let task = tokio::spawn(request);
+                async move {
+                    // A task which panicked still needs to leave its page in a final state.
+                    task.await.unwrap_or_else(|e| {
+                        let mut attempt = Attempt::new(&url, page);
+                        attempt.page.state = PageState::Failed(CrawlError::Task {
+                            url: url.to_owned(),
+                            source: Arc::new(e),
+                        });
+                        attempt.page.attempts = 1;
+                        (attempt, vec![])
+                    })
+                }
+                .boxed()
+            }
+        }
+    }
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// Takes the next url to request from the store's queue. The robots.txt of the url's origin is
+    /// read the first time one of its urls comes up and urls it disallows are skipped.
+    pub(crate) async fn next_pending(
+        &mut self,
+        client: &Client,
+        hosts: &Arc<HostLimiter>,
+    ) -> Result<Option<String>, CrawlError> {
+        while let Some(url) = self.urls.next_pending()? {
+            self.load_robots_for(client, &url, hosts).await;
+            if !self.skip_if_disallowed(&url)? {
+                return Ok(Some(url));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Crawls every pending url using one client. Workers take urls from the store's queue as
+    /// soon as a request finishes and links found on each page or returned by the crawl's
+    /// `PageHandler` go straight back onto it. If
+    /// `events` is set each page and new url is sent to it as it happens and the crawl stops early
+    /// if the receiver is dropped. If `checkpoint` is set every change is written to it as soon as
+    /// each page is done. If the crawl has an `Archive` each page's requests are written to it as
+    /// they finish. The crawl stops with an error if the store fails or the checkpoint, a body or
+    /// a WARC record can't be written.
+    pub(crate) async fn crawl_frontier(
+        self,
+        client: &Client,
+        budget: &Arc<Budget>,
+        hosts: &Arc<HostLimiter>,
+        workers: Workers,
+        checkpoint: Option<Checkpoint>,
+        events: Option<&Sender<CrawlEvent>>,
+    ) -> Result<Self, CrawlError> {
+        let mut new = self;
+        let mut checkpoint = checkpoint;
+        new.checkpoint_all(&mut checkpoint)?;
+        let config = Arc::new(new.config.to_owned());
+        let mut warc = WarcWriter::from_config(&config);
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            while in_flight.len() < workers.size() && !new.stop_at_limit(budget)? {
+                let attempt = match new.next_pending(client, hosts).await? {
+                    Some(url) => match new.urls.get(&url)? {
+                        Some(page) => Attempt::new(&url, page),
+                        None => continue,
+                    },
+                    None => break,
+                };
+                let base_url = &new.base_url;
+                in_flight.push(workers.fetch(client, attempt, base_url, &config, budget, hosts));
+            }
+
+            let (mut attempt, handled) = match in_flight.next().await {
+                Some(fetched) => fetched,
+                None => break,
+            };
+
+            // A redirect is only followed once its hop has been checked. Either way the attempt
+            // goes back to a worker to finish.
+            if let Some(hop) = attempt.redirect().map(|hop| hop.to_owned()) {
+                if !new
+                    .follow_redirect(client, hosts, &attempt.url, &hop)
+                    .await?
+                {
+                    attempt.stop_redirect(&config);
+                }
+                let base_url = &new.base_url;
+                in_flight.push(workers.fetch(client, attempt, base_url, &config, budget, hosts));
+                continue;
+            }
+            let (url, mut page, exchanges) = (attempt.url, attempt.page, attempt.exchanges);
+
+            if let Some(warc) = warc.as_mut() {
+                warc.write(&url, &page, &exchanges)?;
+            }
+            page.stored_body = body::retain(&config.body_retention, &page)?;
+            // The event keeps the body so it's made before the body is taken but only sent once
+            // the page has been recorded.
+            let event = events.and_then(|_| CrawlEvent::from_page(&url, &page));
+            let body = std::mem::take(&mut page.body);
+            new.record_fetched(&url, page, budget)?;
+            new.add_redirect_target(&url)?;
+            let mut found = new.get_urls(&url, &body)?;
+            found.extend(new.add_handled_urls(&url, &handled)?);
+            new.checkpoint_changed(&mut checkpoint)?;
+
+            if let Some(tx) = events {
+                let mut sent = match event {
+                    Some(event) => tx.send(event).await.is_ok(),
+                    None => true,
+                };
+                for url in found.iter() {
+                    if !sent {
+                        break;
+                    }
+                    let event = new
+                        .urls
+                        .get(url)?
+                        .and_then(|page| CrawlEvent::from_link(url, &page));
+                    if let Some(event) = event {
+                        sent = tx.send(event).await.is_ok();
+                    }
+                }
+                // Nobody is listening any more so the crawl stops and finishes as it would at
+                // the end.
+                if !sent {
+                    break;
+                }
+            }
+        }
+
+        // Anything found by the last requests after a limit was reached still needs skipping.
+        new.stop_at_limit(budget)?;
+        new.checkpoint_finished(checkpoint)?;
+        Ok(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::politeness::HostLimiter;
+    use crate::{CrawlError, CrawlStore, PageState, Robots, SimpleCrawler, SkipReason};
+
+    #[tokio::test]
+    async fn next_pending_test() -> Result<(), CrawlError> {
+        let mut simple_crawler = SimpleCrawler::new()
+            .url("https://test.com/a")?
+            .url("https://test.com/private")?
+            .url("https://test.com/b")?
+            .url("https://other.test.com/private")?;
+        // Each origin's rules only apply to its own urls.
+        simple_crawler.robots.insert(
+            "https://test.com".to_owned(),
+            Robots::parse("User-agent: *\nDisallow: /private"),
+        );
+        simple_crawler
+            .robots
+            .insert("https://other.test.com".to_owned(), Robots::allow_all());
+        let client = reqwest::Client::new();
+        let hosts = HostLimiter::new(&simple_crawler);
+
+        simple_crawler
+            .urls
+            .mark_state("https://test.com/b", PageState::Fetched)?;
+
+        assert_eq!(
+            simple_crawler.next_pending(&client, &hosts).await?,
+            Some("https://test.com/a".to_owned())
+        );
+        assert_eq!(
+            simple_crawler.next_pending(&client, &hosts).await?,
+            Some("https://other.test.com/private".to_owned())
+        );
+        assert_eq!(simple_crawler.next_pending(&client, &hosts).await?, None);
+        assert_eq!(
+            simple_crawler.urls["https://test.com/private"].skip_reason(),
+            Some(&SkipReason::RobotsDisallowed)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use super::{CrawlerConfig, Page, PageState};
+use futures::future::BoxFuture;
+use select::document::Document;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The PageHandler trait is how a crawl scrapes pages as well as finding urls. It's set with
+/// `CrawlerBuilder::page_handler` and called for every fetched page before its body is dropped
+/// with the page's url, the page holding its response metadata and body, and the parsed body.
+///
+/// `Document` can't be sent between threads so anything needed from it is read before the
+/// returned future is made. The future can then do slow work such as writing to a database and
+/// runs alongside the crawl's other requests. What it returns is kept on the page as
+/// `Page::data` and its urls are crawled as links of kind `LinkKind::Handler`. For example
+/// ```
+/// use futures::FutureExt;
+/// use simple_crawler::select::document::Document;
+/// use simple_crawler::select::predicate::Name;
+/// use simple_crawler::{Handled, Page, SimpleCrawler};
+///
+/// let handler = |_url: &str, _page: &Page, document: &Document| {
+///     let title = document.find(Name("title")).next().map(|n| n.text());
+///     async move { Handled::new().data("title", &title.unwrap_or_default()) }.boxed()
+/// };
+/// let simple_crawler = SimpleCrawler::builder().page_handler(handler).build();
+/// ```
+pub trait PageHandler: Send + Sync {
+    /// Takes what's wanted from a fetched page.
+    fn handle(&self, url: &str, page: &Page, document: &Document) -> BoxFuture<'static, Handled>;
+}
+
+impl<F> PageHandler for F
+where
+    F: Fn(&str, &Page, &Document) -> BoxFuture<'static, Handled> + Send + Sync,
+{
+    fn handle(&self, url: &str, page: &Page, document: &Document) -> BoxFuture<'static, Handled> {
+        self(url, page, document)
+    }
+}
+
+/// The Handled struct is what a `PageHandler` took from a page for example
+/// `Handled::new().data("price", "9.99").url("/next")`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Handled {
+    data: BTreeMap<String, String>,
+    urls: Vec<String>,
+}
+
+impl Handled {
+    /// Nothing taken from the page.
+    pub fn new() -> Self {
+        Handled::default()
+    }
+
+    /// Keeps a value on the page under a name, replacing any value already kept under it.
+    pub fn data(self, name: &str, value: &str) -> Self {
+        let mut new = self;
+        new.data.insert(name.to_owned(), value.to_owned());
+        new
+    }
+
+    /// Adds a url to crawl. Relative urls are resolved against the page and urls outside the
+    /// crawl's `ScopePolicy` are ignored.
+    pub fn url(self, url: &str) -> Self {
+        let mut new = self;
+        new.urls.push(url.to_owned());
+        new
+    }
+
+    /// The values kept on the page.
+    pub fn values(&self) -> &BTreeMap<String, String> {
+        &self.data
+    }
+
+    /// The urls to crawl.
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    // Keeps the data on the page and hands back the urls.
+    fn keep(self, page: &mut Page) -> Vec<String> {
+        page.data = self.data;
+        self.urls
+    }
+}
+
+/// The handler a crawl was set up with. Handlers can't be compared, printed or saved so two are
+/// only equal if they're the same handler and they're left out when a crawl is serialized.
+#[derive(Clone)]
+pub(crate) struct Handler(pub(crate) Arc<dyn PageHandler>);
+
+impl Handler {
+    pub(crate) fn new(handler: impl PageHandler + 'static) -> Self {
+        Handler(Arc::new(handler))
+    }
+}
+
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PageHandler")
+    }
+}
+
+impl PartialEq for Handler {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Calls the crawl's handler on a page which has just been fetched. The document is dropped before
+// this returns so only the handler's future is ever held across an await.
+fn start(url: &str, page: &Page, config: &CrawlerConfig) -> Option<BoxFuture<'static, Handled>> {
+    let handler = config.handler.as_ref()?;
+    if page.state != PageState::Fetched {
+        return None;
+    }
+    let document = Document::from(page.body());
+    Some(handler.0.handle(url, page, &document))
+}
+
+/// Runs the crawl's handler on a page which has just been fetched, keeping its data on the page
+/// and returning the urls it asked for. Pages which weren't fetched aren't handled.
+pub(crate) async fn handle_page(url: &str, page: &mut Page, config: &CrawlerConfig) -> Vec<String> {
+    match start(url, page, config) {
+        Some(handling) => handling.await.keep(page),
+        None => vec![],
+    }
+}
+
+/// Runs the crawl's handler on a page which has just been fetched, blocking until it's done.
+#[cfg(feature = "blocking")]
+pub(crate) fn handle_page_blocking(
+    url: &str,
+    page: &mut Page,
+    config: &CrawlerConfig,
+) -> Vec<String> {
+    match start(url, page, config) {
+        Some(handling) => futures::executor::block_on(handling).keep(page),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_page, Handled, Handler};
+    use crate::{CrawlerConfig, Page, PageState};
+    use futures::FutureExt;
+    use select::document::Document;
+    use select::predicate::Name;
+
+    #[test]
+    fn handle_page_test() {
+        let handler = |url: &str, _: &Page, document: &Document| {
+            let h1 = document.find(Name("h1")).next().map(|n| n.text());
+            let handled = Handled::new()
+                .data("h1", &h1.unwrap_or_default())
+                .data("url", url)
+                .url("/next");
+            async move { handled }.boxed()
+        };
+        let config = CrawlerConfig {
+            handler: Some(Handler::new(handler)),
+            ..CrawlerConfig::default()
+        };
+        let mut page = Page {
+            body: "<h1>Title</h1>".to_owned(),
+            state: PageState::Fetched,
+            ..Page::new()
+        };
+
+        let urls = handle_page("https://test.com/", &mut page, &config).now_or_never();
+        assert_eq!(urls, Some(vec!["/next".to_owned()]));
+        assert_eq!(page.data()["h1"], "Title");
+        assert_eq!(page.data()["url"], "https://test.com/");
+
+        // Pages which weren't fetched and crawls without a handler are left alone.
+        let mut pending = Page::new();
+        let urls = handle_page("https://test.com/", &mut pending, &config).now_or_never();
+        assert_eq!(urls, Some(vec![]));
+        assert!(pending.data().is_empty());
+        let mut unhandled = Page {
+            body: "<h1>Title</h1>".to_owned(),
+            state: PageState::Fetched,
+            ..Page::new()
+        };
+        let default = CrawlerConfig::default();
+        let urls = handle_page("https://test.com/", &mut unhandled, &default).now_or_never();
+        assert_eq!(urls, Some(vec![]));
+        assert!(unhandled.data().is_empty());
+    }
+}
@@ -1,146 +1,708 @@
 mod blocking;
+mod body;
+mod canonical;
+mod checkpoint;
 mod concurrent;
+mod config;
+mod directives;
+mod error;
+mod export;
+mod extract;
+mod fetch;
+mod frontier;
+mod handler;
+mod limits;
+mod link;
+mod metadata;
 mod non_blocking;
 mod parallel;
+mod politeness;
+mod redirect;
+mod retry;
+mod robots;
+mod scope;
+mod sitemap;
+mod store;
+mod stream;
 mod utils;
+mod warc;
 
-use anyhow::{Context, Result};
+pub use body::{BodyRetention, StoredBody};
+pub use canonical::{Canonicalizer, QueryOrder, QueryPolicy, TrailingSlash, TRACKING_PARAMS};
+pub use config::{CrawlerBuilder, CrawlerConfig, DEFAULT_REQUEST_TIMEOUT};
+pub use directives::RobotsDirectives;
+pub use error::CrawlError;
+pub use export::{ExportFormat, Exporter};
+pub use fetch::DEFAULT_RECORD_HEADERS;
+pub use handler::{Handled, PageHandler};
+pub use limits::Limit;
+pub use link::{Link, LinkKind};
+pub use metadata::PageMetadata;
+pub use politeness::Politeness;
+pub use redirect::{Redirect, RedirectPolicy, RedirectReport};
+pub use retry::RetryPolicy;
+pub use robots::Robots;
+pub use scope::ScopePolicy;
+pub use sitemap::{ChangeFreq, SitemapEntry, SitemapReport};
+pub use store::{CrawlStore, MemoryStore, SqliteStore};
+pub use stream::CrawlEvent;
+pub use warc::Archive;
+
+/// The HTML parser pages are read with, for writing a `PageHandler`.
+pub use select;
+
+use fetch::ResponseInfo;
+use limits::Budget;
 use select::document::Document;
 use select::predicate::Name;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, SystemTime};
 
 /// The SimpleCrawler struct is how this library is used for example:
-/// `let simple_crawler = SimpleCrawler::new()`
+/// `let simple_crawler = SimpleCrawler::new()`. Pages are kept in a `MemoryStore` unless another
+/// `CrawlStore` is given with `SimpleCrawler::with_store`. With the `serde` feature a crawler
+/// using a `MemoryStore` can be serialized along with its settings and pages.
 #[derive(Clone, Debug, PartialEq)]
-pub struct SimpleCrawler {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimpleCrawler<S = MemoryStore> {
     base_url: String,
-    pub urls: HashMap<String, Page>,
+    config: CrawlerConfig,
+    robots: HashMap<String, Robots>,
+    sitemaps: Option<Vec<String>>,
+    limit_reached: Option<Limit>,
+    aliases: HashMap<String, String>,
+    query_variants: HashMap<String, usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    changed: Option<HashSet<String>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    changed_aliases: Option<HashSet<String>>,
+    pub urls: S,
+}
+
+impl Default for SimpleCrawler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SimpleCrawler {
     /// Create a SimpleCrawler for example `let simple_crawler = SimpleCrawler::new()`
     pub fn new() -> Self {
+        SimpleCrawler::with_store(MemoryStore::new())
+    }
+
+    /// Set up a SimpleCrawler with limits for example
+    /// `let simple_crawler = SimpleCrawler::builder().max_pages(100).build()`
+    pub fn builder() -> CrawlerBuilder {
+        CrawlerBuilder::default()
+    }
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// Create a SimpleCrawler which keeps its pages in a store for example
+    /// `let simple_crawler = SimpleCrawler::with_store(SqliteStore::open(path)?)`
+    pub fn with_store(store: S) -> Self {
         SimpleCrawler {
             base_url: "".to_owned(),
-            urls: HashMap::new(),
+            config: CrawlerConfig::default(),
+            robots: HashMap::new(),
+            sitemaps: None,
+            limit_reached: None,
+            aliases: HashMap::new(),
+            query_variants: HashMap::new(),
+            changed: None,
+            changed_aliases: None,
+            urls: store,
         }
     }
 
+    /// The settings the crawl runs with.
+    pub fn config(&self) -> &CrawlerConfig {
+        &self.config
+    }
+
+    /// The limit which stopped the crawl early if one was reached. Urls which weren't requested
+    /// because of it are skipped with `SkipReason::Limit`.
+    pub fn limit_reached(&self) -> Option<Limit> {
+        self.limit_reached
+    }
+
     /// Adds a url to be crawled for example `let simple_crawler = SimpleCrawler::new().url(&str)`
-    /// the first url is the base url. Subsequent urls will be ignored if not on the same domain as
-    /// the first url or not relative paths.
-    pub fn url(self, url: &str) -> Result<Self> {
+    /// the first url is the base url. Urls whose path or patterns are outside the crawl's
+    /// `ScopePolicy` are ignored.
+    pub fn url(self, url: &str) -> Result<Self, CrawlError> {
         let mut new = self;
 
         // Base url needed in case relative paths are added.
         // and to not include external domains
-        let new_base_url =
-            utils::check_base_url(url).with_context(|| format!("Base URL invalid"))?;
+        let new_base_url = utils::check_base_url(url)?;
         if new.base_url.is_empty() {
             new.base_url = new_base_url.to_owned();
         }
 
         // These are the lookup urls.
-        let new_url = utils::normalise_url(&new_base_url, url);
-        if new_url.is_some() {
-            new.urls.insert(new_url.unwrap().to_owned(), Page::new());
+        if let Some(new_url) = utils::normalise_url(&new_base_url, &new_base_url, url, &new.config)
+        {
+            new.urls.insert_if_absent(&new_url, Page::new())?;
         }
 
         Ok(new)
     }
 
-    fn get_urls(self) -> Result<Self> {
+    /// Pages which named another url with `<link rel="canonical">` mapped to that url and urls
+    /// fetched pages were redirected to mapped to the page. Links found to an alias are recorded
+    /// against the url it's mapped to.
+    pub fn aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    /// Stops the crawl if a limit has been reached. Any urls still queued are skipped so every
+    /// page ends in a final state. Returns true if the crawl should stop.
+    fn stop_at_limit(&mut self, budget: &Budget) -> Result<bool, CrawlError> {
+        let limit = match budget.reached() {
+            Some(limit) => limit,
+            None => return Ok(false),
+        };
+        let mut stopped = false;
+        while let Some(url) = self.urls.next_pending()? {
+            self.urls
+                .mark_state(&url, PageState::Skipped(SkipReason::Limit(limit)))?;
+            self.mark_changed(&url);
+            stopped = true;
+        }
+        if stopped {
+            self.limit_reached = Some(limit);
+        }
+        Ok(stopped)
+    }
+
+    /// Stores what was learnt from requesting a url. A page which is still pending wasn't
+    /// requested because a limit was reached so it's skipped.
+    fn record_fetched(&mut self, url: &str, page: Page, budget: &Budget) -> Result<(), CrawlError> {
+        let limit = budget.reached();
+        let mut fetched = Some(page);
+        let mut skipped = false;
+        self.urls.modify(url, &mut |known| {
+            if let Some(fetched) = fetched.take() {
+                known.update(fetched);
+            }
+            if let (true, Some(limit)) = (known.is_pending(), limit) {
+                known.state = PageState::Skipped(SkipReason::Limit(limit));
+                skipped = true;
+            }
+        })?;
+        if skipped {
+            self.limit_reached = limit;
+        }
+        self.mark_changed(url);
+        Ok(())
+    }
+
+    /// Records where a fetched page was redirected to as an alias of the page so it isn't fetched
+    /// again under its own url. The target is skipped if it's waiting to be fetched.
+    fn add_redirect_target(&mut self, source: &str) -> Result<(), CrawlError> {
+        let page = match self.urls.get(source)? {
+            Some(page) if page.state == PageState::Fetched && !page.redirects().is_empty() => page,
+            _ => return Ok(()),
+        };
+        let final_url = page.final_url().unwrap_or(source);
+        let target = match utils::normalise_url(&self.base_url, final_url, final_url, &self.config)
+        {
+            Some(target) if target != source => target,
+            _ => return Ok(()),
+        };
+
+        let mut skipped = false;
+        self.urls.modify(&target, &mut |page| {
+            if page.is_pending() {
+                page.state = PageState::Skipped(SkipReason::Redirected);
+                skipped = true;
+            }
+        })?;
+        if skipped {
+            self.mark_changed(&target);
+        }
+        self.add_alias(target, source.to_owned());
+        Ok(())
+    }
+
+    // Notes that a page has changed so it's written to the checkpoint. Changes are only tracked
+    // while a crawl is being checkpointed.
+    fn mark_changed(&mut self, url: &str) {
+        if let Some(changed) = self.changed.as_mut() {
+            changed.insert(url.to_owned());
+        }
+    }
+
+    // Maps a url to the page it's an alias of. Aliases are tracked apart from pages for the
+    // checkpoint as a redirect target usually has no page of its own.
+    fn add_alias(&mut self, url: String, canonical: String) {
+        if let Some(changed) = self.changed_aliases.as_mut() {
+            changed.insert(url.to_owned());
+        }
+        self.aliases.insert(url, canonical);
+    }
+
+    /// Adds the links found in a fetched page's body as new pages and returns the urls which
+    /// weren't already known. The page's metadata is read at the same time if it's wanted. The
+    /// body is never stored.
+    fn get_urls(&mut self, source: &str, body: &str) -> Result<Vec<String>, CrawlError> {
         let base_url = self.base_url.to_owned();
-        let mut new = self;
+        let config = self.config.to_owned();
+        let new = self;
+        let mut found = vec![];
+
+        let page = match new.urls.get(source)? {
+            Some(page) => page,
+            None => return Ok(found),
+        };
+        let depth = page.depth + 1;
+
+        // Links are relative to where the page ended up after redirects.
+        let document = Document::from(body);
+        let headers = page
+            .response
+            .as_ref()
+            .map(|r| r.robots_tags.as_slice())
+            .unwrap_or(&[]);
+        let directives = RobotsDirectives::from_headers(headers, &config.user_agent).merge(
+            RobotsDirectives::from_document(&document, &config.user_agent),
+        );
+        let base_href = document
+            .find(Name("base"))
+            .find_map(|node| node.attr("href"));
+        let page_url = utils::page_base_url(page.final_url().unwrap_or(source), base_href);
+        let normalise = |href: &str| utils::normalise_url(&base_url, &page_url, href, &config);
+
+        let canonical = match config.canonicalizer.follows_rel_canonical() {
+            true => document
+                .find(Name("link"))
+                .filter(|node| {
+                    node.attr("rel")
+                        .unwrap_or_default()
+                        .split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("canonical"))
+                })
+                .find_map(|node| node.attr("href"))
+                .and_then(normalise)
+                .filter(|canonical| canonical != source),
+            false => None,
+        };
+        let mut metadata = match config.extract_metadata {
+            true => Some(PageMetadata::from_document(&document, &page_url)),
+            false => None,
+        };
+        new.urls.modify(source, &mut |page| {
+            page.robots_directives = directives;
+            if canonical.is_some() {
+                page.canonical_url = canonical.to_owned();
+            }
+            if metadata.is_some() {
+                page.metadata = metadata.take();
+            }
+        })?;
+        new.mark_changed(source);
 
-        for (_, page) in new.urls.to_owned().iter_mut() {
-            Document::from(page.body.as_ref())
-                .find(Name("a"))
-                .filter_map(|n| n.attr("href"))
-                .for_each(|v| {
-                    let new_url = utils::normalise_url(&base_url, v);
-                    if new_url.is_some() {
-                        let nu = new_url.unwrap();
-                        if !new.urls.contains_key(&nu) {
-                            new.urls.insert(nu, Page::new());
-                        }
-                    }
-                });
-            // TODO need to better manage stack usage at this point.
-            // Clear body variable as it's no longer needed once links have been extracted.
-            *page = Page::with_values("", &true);
+        if let Some(canonical) = canonical {
+            new.add_alias(source.to_owned(), canonical.to_owned());
+            // The canonical url is always followed whatever kinds of link are.
+            let link = Link::new(source, "", Some("canonical"), LinkKind::Link);
+            new.add_link(canonical, link, depth, None, &mut found)?;
         }
 
-        Ok(new)
+        for found_link in extract::find_links(&document, &config.extract_links) {
+            if let Some(nu) = normalise(&found_link.href) {
+                let link = Link::new(source, &found_link.text, found_link.rel, found_link.kind);
+                let skip = if !config.follow_links.contains(&link.kind) {
+                    Some(SkipReason::NotFollowed)
+                } else if config.obey_robots_directives
+                    && (directives.nofollow() || link.nofollow())
+                {
+                    Some(SkipReason::NoFollow)
+                } else {
+                    None
+                };
+                new.add_link(nu, link, depth, skip, &mut found)?;
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Adds the urls a `PageHandler` asked for on a fetched page and returns the ones which weren't
+    /// already known. They're followed whatever kinds of link are.
+    fn add_handled_urls(
+        &mut self,
+        source: &str,
+        urls: &[String],
+    ) -> Result<Vec<String>, CrawlError> {
+        let mut found = vec![];
+        let page = match self.urls.get(source)? {
+            Some(page) => page,
+            None => return Ok(found),
+        };
+        let page_url = page.final_url().unwrap_or(source).to_owned();
+        for url in urls {
+            if let Some(nu) = utils::normalise_url(&self.base_url, &page_url, url, &self.config) {
+                let link = Link::new(source, "", None, LinkKind::Handler);
+                self.add_link(nu, link, page.depth + 1, None, &mut found)?;
+            }
+        }
+        Ok(found)
+    }
+
+    // Records a link to a url, adding the url as a new page if it isn't known or skipping it with
+    // `skip` if the link isn't followed. Links to an alias are recorded against its canonical
+    // url. A url which was only recorded is crawled once a link which is followed is found to it.
+    fn add_link(
+        &mut self,
+        url: String,
+        link: Link,
+        depth: u32,
+        skip: Option<SkipReason>,
+        found: &mut Vec<String>,
+    ) -> Result<(), CrawlError> {
+        let url = self.aliases.get(&url).cloned().unwrap_or(url);
+        self.mark_changed(&url);
+        let source = link.source.to_owned();
+        let mut followed = false;
+        let known = self.urls.modify(&url, &mut |page| {
+            let not_followed = matches!(
+                page.skip_reason(),
+                Some(SkipReason::NotFollowed | SkipReason::NoFollow)
+            );
+            if skip.is_none() && not_followed {
+                page.state = PageState::Pending;
+                page.discovered_by = Some(link.to_owned());
+                followed = true;
+            }
+            if !page.referrers.contains(&source) {
+                page.referrers.push(source.to_owned());
+            }
+        })?;
+        if followed {
+            found.push(url.to_owned());
+        }
+        if known {
+            return Ok(());
+        }
+
+        // Every url with a query counts against the variants of its path.
+        let max_query_variants = self.config.max_query_variants;
+        let variants = url
+            .split_once('?')
+            .map(|(path, _)| self.query_variants.entry(path.to_owned()).or_insert(0));
+        let too_many_variants = match (variants, max_query_variants) {
+            (Some(count), Some(max)) if *count >= max => true,
+            (Some(count), _) => {
+                *count += 1;
+                false
+            }
+            _ => false,
+        };
+        let page = match self.config.max_depth {
+            Some(max) if depth > max => Page::skipped(SkipReason::Limit(Limit::MaxDepth)),
+            _ if too_many_variants => Page::skipped(SkipReason::Limit(Limit::MaxQueryVariants)),
+            _ => match skip {
+                Some(reason) => Page::skipped(reason),
+                None => Page::new(),
+            },
+        };
+        let page = Page {
+            depth,
+            referrers: vec![source],
+            discovered_by: Some(link),
+            ..page
+        };
+        self.urls.insert_if_absent(&url, page)?;
+        found.push(url);
+        Ok(())
     }
 }
 
+/// The user agent sent when one hasn't been set with `CrawlerBuilder::user_agent`.
+pub const DEFAULT_USER_AGENT: &str = concat!("simple_crawler/", env!("CARGO_PKG_VERSION"));
+
 /// The Page struct stores all the text extracted from crawled web pages temporarily until urls have
-/// been extracted and the status of whether the page has been crawled or not.
+/// been extracted, the state of the page in the crawl and what was learnt from the response it was
+/// fetched with.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Page {
     body: String,
-    crawled: bool,
+    state: PageState,
+    depth: u32,
+    referrers: Vec<String>,
+    discovered_by: Option<Link>,
+    canonical_url: Option<String>,
+    robots_directives: RobotsDirectives,
+    sitemap: Option<SitemapEntry>,
+    attempts: u32,
+    response: Option<ResponseInfo>,
+    metadata: Option<PageMetadata>,
+    data: BTreeMap<String, String>,
+    stored_body: Option<StoredBody>,
+}
+
+/// Where a page is in the crawl. Every page ends a crawl as `Fetched`, `Failed` or `Skipped`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum PageState {
+    /// The page is waiting to be requested.
+    Pending,
+    /// The page was fetched.
+    Fetched,
+    /// Every attempt to fetch the page failed.
+    Failed(CrawlError),
+    /// The page was never requested.
+    Skipped(SkipReason),
+}
+
+/// The reason a url was never requested.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SkipReason {
+    /// The site's robots.txt disallows the url for the crawler's user agent.
+    RobotsDisallowed,
+    /// A crawl limit was reached before the url was requested.
+    Limit(Limit),
+    /// The url was only found through kinds of link which aren't followed.
+    NotFollowed,
+    /// The url was only found through links marked `nofollow`, `ugc` or `sponsored` or on pages
+    /// whose robots directives say `nofollow`.
+    NoFollow,
+    /// A page which was already fetched redirected to the url so it would be fetched twice.
+    Redirected,
 }
 
 impl Page {
     fn new() -> Self {
         Page {
             body: "".to_owned(),
-            crawled: false,
+            state: PageState::Pending,
+            depth: 0,
+            referrers: vec![],
+            discovered_by: None,
+            canonical_url: None,
+            robots_directives: RobotsDirectives::default(),
+            sitemap: None,
+            attempts: 0,
+            response: None,
+            metadata: None,
+            data: BTreeMap::new(),
+            stored_body: None,
         }
     }
 
-    fn with_values(body: &str, crawled: &bool) -> Self {
+    fn skipped(reason: SkipReason) -> Self {
         Page {
-            body: body.to_owned(),
-            crawled: crawled.to_owned(),
+            state: PageState::Skipped(reason),
+            ..Page::new()
+        }
+    }
+
+    /// Where the page is in the crawl.
+    pub fn state(&self) -> &PageState {
+        &self.state
+    }
+
+    /// Whether the page has been fetched.
+    pub fn crawled(&self) -> bool {
+        self.state == PageState::Fetched
+    }
+
+    /// Why the page was never requested if it was skipped.
+    pub fn skip_reason(&self) -> Option<&SkipReason> {
+        match &self.state {
+            PageState::Skipped(reason) => Some(reason),
+            _ => None,
         }
     }
+
+    /// Why the page couldn't be fetched if it failed.
+    pub fn error(&self) -> Option<&CrawlError> {
+        match &self.state {
+            PageState::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The response body. It's only kept until the links on the page have been found so it's empty
+    /// on the pages of a finished crawl but set on `CrawlEvent::PageFetched` pages. Bodies kept
+    /// after that are in `Page::stored_body`.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// The body kept once the page's links were found if `CrawlerBuilder::body_retention` asked
+    /// for it to be.
+    pub fn stored_body(&self) -> Option<&StoredBody> {
+        self.stored_body.as_ref()
+    }
+
+    /// How many links the page was found away from a seed url. Seeds are at depth 0.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The urls of every crawled page which links to this page in the order they were found.
+    pub fn referrers(&self) -> &[String] {
+        &self.referrers
+    }
+
+    /// The link the page was first found through, or the first followed link if the page was
+    /// found through links which aren't followed first. This is `None` for seed urls.
+    pub fn discovered_by(&self) -> Option<&Link> {
+        self.discovered_by.as_ref()
+    }
+
+    /// The url the page named with `<link rel="canonical">` if it wasn't the page's own url.
+    pub fn canonical_url(&self) -> Option<&str> {
+        self.canonical_url.as_deref()
+    }
+
+    /// What the page's `<meta name="robots">` and X-Robots-Tag headers asked crawlers to do. These
+    /// are read when the page's links are found so they're only set on fetched pages.
+    pub fn robots_directives(&self) -> &RobotsDirectives {
+        &self.robots_directives
+    }
+
+    /// What the sitemap the page was listed in said about it. If it was listed in more than one
+    /// the first sitemap read is used.
+    pub fn sitemap(&self) -> Option<&SitemapEntry> {
+        self.sitemap.as_ref()
+    }
+
+    /// How many times the page was requested.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The HTTP status code of the response.
+    pub fn status(&self) -> Option<u16> {
+        self.response.as_ref().map(|r| r.status)
+    }
+
+    /// The url the page was fetched from after following any redirects. This is the url of the
+    /// last redirect if the chain wasn't followed to its end.
+    pub fn final_url(&self) -> Option<&str> {
+        self.response.as_ref().map(|r| r.final_url.as_str())
+    }
+
+    /// Every redirect followed to fetch the page in order, including the last one if it wasn't
+    /// followed.
+    pub fn redirects(&self) -> &[Redirect] {
+        self.response
+            .as_ref()
+            .map(|r| r.redirects.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The recorded response headers as lower case name and value pairs. Which headers are kept is
+    /// set with `CrawlerBuilder::record_headers`.
+    pub fn headers(&self) -> &[(String, String)] {
+        self.response
+            .as_ref()
+            .map(|r| r.headers.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The value of a recorded response header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers()
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The Content-Type of the response.
+    pub fn content_type(&self) -> Option<&str> {
+        self.response
+            .as_ref()
+            .and_then(|r| r.content_type.as_deref())
+    }
+
+    /// The Content-Length of the response, or the length of the body if the header wasn't sent.
+    pub fn content_length(&self) -> Option<u64> {
+        self.response.as_ref().and_then(|r| r.content_length)
+    }
+
+    /// How long the request took from sending it until the whole body was read.
+    pub fn latency(&self) -> Option<Duration> {
+        self.response.as_ref().map(|r| r.latency)
+    }
+
+    /// When the request was sent.
+    pub fn fetched_at(&self) -> Option<SystemTime> {
+        self.response.as_ref().map(|r| r.fetched_at)
+    }
+
+    /// The page's title, description, headings and other metadata. It's only read from fetched
+    /// pages when `CrawlerBuilder::extract_metadata` is on.
+    pub fn metadata(&self) -> Option<&PageMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// What the crawl's `PageHandler` kept from the page.
+    pub fn data(&self) -> &BTreeMap<String, String> {
+        &self.data
+    }
+
+    /// Takes what was learnt from requesting the page. Referrers found while the request was in
+    /// flight are kept and the body is left behind.
+    fn update(&mut self, fetched: Page) {
+        self.state = fetched.state;
+        self.attempts = fetched.attempts;
+        self.response = fetched.response;
+        self.data = fetched.data;
+        self.stored_body = fetched.stored_body;
+    }
+
+    fn is_pending(&self) -> bool {
+        self.state == PageState::Pending
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Page;
     use super::SimpleCrawler;
-    use anyhow::Result;
-    use std::collections::HashMap;
+    use super::{CrawlStore, Link, LinkKind, Page};
+    use crate::CrawlError;
 
-    fn add_url_test_data(url: &str) -> SimpleCrawler {
-        let mut urls = HashMap::new();
-        urls.insert(
-            url.to_owned(),
-            Page {
-                body: "".to_owned(),
-                crawled: false,
-            },
-        );
-        SimpleCrawler {
+    fn add_url_test_data(url: &str) -> Result<SimpleCrawler, CrawlError> {
+        // Urls are keyed by their canonical form which always has a path.
+        let mut simple_crawler = SimpleCrawler {
             base_url: format!("{}/", url.to_owned()),
-            urls,
-        }
+            ..SimpleCrawler::new()
+        };
+        simple_crawler
+            .urls
+            .insert_if_absent(&format!("{}/", url), Page::new())?;
+        Ok(simple_crawler)
     }
 
-    fn get_urls_test_data(url: &str) -> SimpleCrawler {
-        let mut urls = HashMap::new();
-        urls.insert(
-            url.to_owned(),
-            Page {
-                body: "<a href=\"https://test.com/test_url\">aaa</a>".to_owned(),
-                crawled: false,
-            },
-        );
-        SimpleCrawler {
+    fn get_urls_test_data(url: &str) -> Result<SimpleCrawler, CrawlError> {
+        let mut simple_crawler = SimpleCrawler {
             base_url: format!("{}/", url.to_owned()),
-            urls,
-        }
+            ..SimpleCrawler::new()
+        };
+        simple_crawler.urls.insert_if_absent(url, Page::new())?;
+        Ok(simple_crawler)
     }
 
     #[test]
-    fn add_url_test() -> Result<()> {
+    fn add_url_test() -> Result<(), CrawlError> {
         // test data
         let url = "https://test.com";
-        let test_simple_creator = add_url_test_data(url);
+        let test_simple_creator = add_url_test_data(url)?;
 
         // created object
         let simple_crawler = SimpleCrawler::new().url(url)?;
@@ -149,23 +711,34 @@ mod tests {
     }
 
     #[test]
-    fn get_urls_test() -> Result<()> {
+    fn get_urls_test() -> Result<(), CrawlError> {
         // test data
         let url = "https://test.com";
-        let mut test_simple_creator = get_urls_test_data(url);
-        test_simple_creator.urls.insert(
-            "https://test.com/test_url".to_owned(),
+        let mut test_simple_creator = get_urls_test_data(url)?;
+        test_simple_creator.urls.insert_if_absent(
+            "https://test.com/test_url",
             Page {
-                body: "".to_owned(),
-                crawled: false,
+                depth: 1,
+                referrers: vec![url.to_owned()],
+                discovered_by: Some(Link {
+                    source: url.to_owned(),
+                    text: "aaa".to_owned(),
+                    rel: vec!["next".to_owned()],
+                    kind: LinkKind::Anchor,
+                }),
+                ..Page::new()
             },
-        );
+        )?;
 
         // created object
-        let simple_creator = get_urls_test_data(url).get_urls()?;
+        let mut simple_creator = get_urls_test_data(url)?;
+        let found = simple_creator.get_urls(
+            url,
+            "<a href=\"https://test.com/test_url\" rel=\"Next\">aaa</a>",
+        )?;
 
+        assert_eq!(found, ["https://test.com/test_url"]);
         assert_eq!(test_simple_creator, simple_creator);
-
         Ok(())
     }
 }
@@ -0,0 +1,158 @@
+use super::CrawlerConfig;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The crawl limits set with `SimpleCrawler::builder()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Limit {
+    /// `max_pages` urls were requested.
+    MaxPages,
+    /// The url was found further than `max_depth` links from a seed.
+    MaxDepth,
+    /// The crawl ran for `max_duration`.
+    MaxDuration,
+    /// `max_bytes` body bytes were downloaded.
+    MaxBytes,
+    /// `max_query_variants` urls with a query were already found for the url's path.
+    MaxQueryVariants,
+}
+
+/// What's left of the page, time and byte limits for a single crawl. It's shared between every
+/// request so concurrent and parallel requests count against the same totals.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    started: Instant,
+    pages: AtomicUsize,
+    bytes: AtomicU64,
+    max_pages: Option<usize>,
+    max_duration: Option<Duration>,
+    max_bytes: Option<u64>,
+    request_timeout: Duration,
+}
+
+impl Budget {
+    pub(crate) fn new(config: &CrawlerConfig) -> Arc<Self> {
+        Arc::new(Budget {
+            started: Instant::now(),
+            pages: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            max_pages: config.max_pages,
+            max_duration: config.max_duration,
+            max_bytes: config.max_bytes,
+            request_timeout: config.request_timeout,
+        })
+    }
+
+    /// Takes a page from the budget before a url is requested. Returns the limit which has been
+    /// reached if the url shouldn't be requested.
+    pub(crate) fn start_page(&self) -> Result<(), Limit> {
+        if let Some(limit) = self.time_or_bytes_reached() {
+            return Err(limit);
+        }
+        let taken = self
+            .pages
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pages| {
+                match self.max_pages {
+                    Some(max) if pages >= max => None,
+                    _ => Some(pages + 1),
+                }
+            });
+        taken.map(|_| ()).map_err(|_| Limit::MaxPages)
+    }
+
+    /// Whether a url which failed can be requested again after waiting `delay`. A retry is part
+    /// of the page already taken from the budget so only the time and byte limits are checked,
+    /// including whether the wait would run past `max_duration`.
+    pub(crate) fn allows_retry(&self, delay: Duration) -> bool {
+        let out_of_time = self
+            .max_duration
+            .is_some_and(|max| self.started.elapsed() + delay >= max);
+        !out_of_time && self.time_or_bytes_reached().is_none()
+    }
+
+    /// How long the next request can take. It's the crawl's request timeout unless less time than
+    /// that is left before `max_duration`.
+    pub(crate) fn request_timeout(&self) -> Duration {
+        match self.max_duration {
+            Some(max) => max
+                .saturating_sub(self.started.elapsed())
+                .min(self.request_timeout),
+            None => self.request_timeout,
+        }
+    }
+
+    /// Counts body bytes downloaded.
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    /// The limit which stops any more urls being requested.
+    pub(crate) fn reached(&self) -> Option<Limit> {
+        self.time_or_bytes_reached()
+            .or_else(|| match self.max_pages {
+                Some(max) if self.pages.load(Ordering::SeqCst) >= max => Some(Limit::MaxPages),
+                _ => None,
+            })
+    }
+
+    fn time_or_bytes_reached(&self) -> Option<Limit> {
+        match (self.max_duration, self.max_bytes) {
+            (Some(max), _) if self.started.elapsed() >= max => Some(Limit::MaxDuration),
+            (_, Some(max)) if self.bytes.load(Ordering::SeqCst) >= max => Some(Limit::MaxBytes),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Budget, Limit};
+    use crate::SimpleCrawler;
+    use std::time::Duration;
+
+    #[test]
+    fn budget_test() {
+        let config = SimpleCrawler::builder().max_pages(2).max_bytes(10).build();
+        let budget = Budget::new(config.config());
+
+        assert_eq!(budget.start_page(), Ok(()));
+        assert_eq!(budget.reached(), None);
+        assert_eq!(budget.start_page(), Ok(()));
+        assert_eq!(budget.start_page(), Err(Limit::MaxPages));
+        assert_eq!(budget.reached(), Some(Limit::MaxPages));
+
+        budget.add_bytes(10);
+        assert_eq!(budget.start_page(), Err(Limit::MaxBytes));
+
+        // Retries are allowed past the page limit but not past the byte limit.
+        assert!(!budget.allows_retry(Duration::from_secs(0)));
+        let config = SimpleCrawler::builder()
+            .max_pages(1)
+            .max_duration(Duration::from_secs(60))
+            .build();
+        let budget = Budget::new(config.config());
+        assert_eq!(budget.start_page(), Ok(()));
+        assert!(budget.allows_retry(Duration::from_secs(1)));
+        assert!(!budget.allows_retry(Duration::from_secs(60)));
+        // Requests time out at the request timeout or when the crawl runs out of time.
+        assert_eq!(budget.request_timeout(), crate::DEFAULT_REQUEST_TIMEOUT);
+        let config = SimpleCrawler::builder()
+            .max_duration(Duration::from_secs(10))
+            .build();
+        assert!(Budget::new(config.config()).request_timeout() <= Duration::from_secs(10));
+
+        let config = SimpleCrawler::builder()
+            .max_duration(Duration::from_secs(0))
+            .build();
+        assert_eq!(
+            Budget::new(config.config()).start_page(),
+            Err(Limit::MaxDuration)
+        );
+    }
+}
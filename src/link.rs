@@ -0,0 +1,110 @@
+/// Where on a page a link was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LinkKind {
+    /// `<a href>`.
+    Anchor,
+    /// `<area href>` in an image map.
+    Area,
+    /// `<link href>`, for example canonical, alternate, next, prev or a stylesheet.
+    Link,
+    /// `<iframe src>` or `<frame src>`.
+    Frame,
+    /// `<img src>` or `<img srcset>`.
+    Image,
+    /// `<script src>`.
+    Script,
+    /// `<source src>` or `<source srcset>` in a picture, video or audio element.
+    Source,
+    /// The action of a `<form>` submitted with GET.
+    Form,
+    /// `<meta http-equiv="refresh" content="0; url=...">`.
+    MetaRefresh,
+    /// `url(...)` in a `<style>` element or `style` attribute.
+    Css,
+    /// A url returned by the crawl's `PageHandler`.
+    Handler,
+}
+
+impl LinkKind {
+    /// Every kind of link.
+    pub const ALL: &'static [LinkKind] = &[
+        LinkKind::Anchor,
+        LinkKind::Area,
+        LinkKind::Link,
+        LinkKind::Frame,
+        LinkKind::Image,
+        LinkKind::Script,
+        LinkKind::Source,
+        LinkKind::Form,
+        LinkKind::MetaRefresh,
+        LinkKind::Css,
+        LinkKind::Handler,
+    ];
+
+    /// The kinds of link which lead to other pages rather than assets. These are followed unless
+    /// changed with `CrawlerBuilder::follow_links`.
+    pub const PAGES: &'static [LinkKind] = &[
+        LinkKind::Anchor,
+        LinkKind::Area,
+        LinkKind::Frame,
+        LinkKind::MetaRefresh,
+    ];
+}
+
+/// The Link struct records a link found on a crawled page.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Link {
+    pub(crate) source: String,
+    pub(crate) text: String,
+    pub(crate) rel: Vec<String>,
+    pub(crate) kind: LinkKind,
+}
+
+impl Link {
+    pub(crate) fn new(source: &str, text: &str, rel: Option<&str>, kind: LinkKind) -> Self {
+        Link {
+            source: source.to_owned(),
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            rel: rel
+                .unwrap_or("")
+                .split_whitespace()
+                .map(|r| r.to_lowercase())
+                .collect(),
+            kind,
+        }
+    }
+
+    /// The url of the page the link was found on.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The text of the link with whitespace collapsed.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The lower case values of the link's `rel` attribute.
+    pub fn rel(&self) -> &[String] {
+        &self.rel
+    }
+
+    /// Whether the link's `rel` is `nofollow`, `ugc` or `sponsored`, asking crawlers not to
+    /// follow it.
+    pub fn nofollow(&self) -> bool {
+        self.rel
+            .iter()
+            .any(|r| matches!(r.as_str(), "nofollow" | "ugc" | "sponsored"))
+    }
+
+    /// Where on the page the link was found.
+    pub fn kind(&self) -> LinkKind {
+        self.kind
+    }
+}
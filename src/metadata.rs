@@ -0,0 +1,223 @@
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Name, Or};
+use url::Url;
+
+/// The PageMetadata struct holds what a page said about itself in its `<head>` and headings. It's
+/// read while the page's links are found when `CrawlerBuilder::extract_metadata` is on so the
+/// body never needs to be kept. Text is trimmed with runs of whitespace collapsed and links are
+/// resolved against the url the page was fetched from.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageMetadata {
+    pub(crate) title: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) robots: Option<String>,
+    pub(crate) headings: Vec<(u8, String)>,
+    pub(crate) canonical: Option<String>,
+    pub(crate) hreflang: Vec<(String, String)>,
+    pub(crate) open_graph: Vec<(String, String)>,
+    pub(crate) twitter: Vec<(String, String)>,
+    pub(crate) json_ld: Vec<String>,
+}
+
+impl PageMetadata {
+    /// The text of the page's `<title>`.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The content of the page's `<meta name="description">`.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The content of the page's `<meta name="robots">` elements joined with commas.
+    pub fn robots(&self) -> Option<&str> {
+        self.robots.as_deref()
+    }
+
+    /// The level and text of every `<h1>`, `<h2>` and `<h3>` in the order they're on the page.
+    pub fn headings(&self) -> &[(u8, String)] {
+        &self.headings
+    }
+
+    /// The text of the page's headings at one level, for example `metadata.headings_at(1)` for its
+    /// `<h1>`s.
+    pub fn headings_at(&self, level: u8) -> impl Iterator<Item = &str> {
+        self.headings
+            .iter()
+            .filter(move |(l, _)| *l == level)
+            .map(|(_, text)| text.as_str())
+    }
+
+    /// The url of the page's `<link rel="canonical">` whether or not it's the page's own url.
+    pub fn canonical(&self) -> Option<&str> {
+        self.canonical.as_deref()
+    }
+
+    /// The language and url of every `<link rel="alternate" hreflang>`.
+    pub fn hreflang(&self) -> &[(String, String)] {
+        &self.hreflang
+    }
+
+    /// The property and content of every `og:` meta tag, for example `("og:title", "Home")`.
+    /// Properties can be repeated, for example a page with more than one `og:image`.
+    pub fn open_graph(&self) -> &[(String, String)] {
+        &self.open_graph
+    }
+
+    /// The name and content of every `twitter:` meta tag, for example `("twitter:card", "summary")`.
+    pub fn twitter(&self) -> &[(String, String)] {
+        &self.twitter
+    }
+
+    /// The text of every `<script type="application/ld+json">`. It isn't parsed or checked.
+    pub fn json_ld(&self) -> &[String] {
+        &self.json_ld
+    }
+
+    /// Reads the metadata of a parsed page fetched from `page_url`.
+    pub(crate) fn from_document(document: &Document, page_url: &str) -> Self {
+        let base = Url::parse(page_url).ok();
+        let resolve = |href: &str| match base.as_ref().and_then(|b| b.join(href.trim()).ok()) {
+            Some(url) => url.to_string(),
+            None => href.trim().to_owned(),
+        };
+        let links = |rel: &'static str| {
+            document.find(Name("link")).filter(move |node| {
+                node.attr("rel")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case(rel))
+            })
+        };
+        let metas = |attr: &'static str, prefix: &'static str| {
+            document
+                .find(Name("meta"))
+                .filter_map(move |node| {
+                    let name = node.attr(attr)?.trim().to_lowercase();
+                    let content = node.attr("content")?;
+                    Some((name, collapse(content)))
+                })
+                .filter(move |(name, _)| name.starts_with(prefix))
+        };
+        let meta = |name: &'static str| {
+            metas("name", name)
+                .filter(move |(n, _)| n == name)
+                .map(|(_, content)| content)
+        };
+
+        let robots = meta("robots").collect::<Vec<_>>();
+        PageMetadata {
+            title: document.find(Name("title")).next().map(text),
+            description: meta("description").next(),
+            robots: Some(robots.join(", ")).filter(|_| !robots.is_empty()),
+            headings: document
+                .find(Or(Name("h1"), Or(Name("h2"), Name("h3"))))
+                .filter_map(|node| Some((heading_level(node.name()?)?, text(node))))
+                .collect(),
+            canonical: links("canonical")
+                .find_map(|node| node.attr("href"))
+                .map(resolve),
+            hreflang: links("alternate")
+                .filter_map(|node| Some((node.attr("hreflang")?, node.attr("href")?)))
+                .map(|(lang, href)| (lang.trim().to_owned(), resolve(href)))
+                .collect(),
+            // Some sites give Open Graph tags a name rather than a property.
+            open_graph: metas("property", "og:")
+                .chain(metas("name", "og:"))
+                .collect(),
+            twitter: metas("name", "twitter:")
+                .chain(metas("property", "twitter:"))
+                .collect(),
+            json_ld: document
+                .find(Name("script"))
+                .filter(|node| {
+                    node.attr("type")
+                        .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/ld+json"))
+                })
+                .map(|node| node.text().trim().to_owned())
+                .collect(),
+        }
+    }
+}
+
+fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        _ => None,
+    }
+}
+
+fn text(node: Node) -> String {
+    collapse(&node.text())
+}
+
+fn collapse(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageMetadata;
+    use select::document::Document;
+
+    #[test]
+    fn page_metadata_test() {
+        let document = Document::from(
+            "<html><head>\
+                <title> Home \n page </title>\
+                <meta name=\"Description\" content=\"All about us\">\
+                <meta name=\"robots\" content=\"noindex\">\
+                <meta name=\"robots\" content=\"nofollow\">\
+                <link rel=\"canonical\" href=\"/home\">\
+                <link rel=\"alternate\" hreflang=\"fr\" href=\"https://test.com/fr/\">\
+                <link rel=\"alternate\" hreflang=\"x-default\" href=\"/\">\
+                <link rel=\"alternate\" type=\"application/rss+xml\" href=\"/feed\">\
+                <meta property=\"og:title\" content=\"Home\">\
+                <meta property=\"og:image\" content=\"/a.png\">\
+                <meta property=\"og:image\" content=\"/b.png\">\
+                <meta name=\"twitter:card\" content=\"summary\">\
+                <script type=\"application/ld+json\"> {\"@type\": \"Organization\"} </script>\
+                <script>var a = 1;</script>\
+            </head><body>\
+                <h1>Welcome</h1><h2>About</h2><h4>Small</h4><h3>Team</h3><h2>Contact</h2>\
+            </body></html>",
+        );
+        let metadata = PageMetadata::from_document(&document, "https://test.com/index.html");
+
+        assert_eq!(metadata.title(), Some("Home page"));
+        assert_eq!(metadata.description(), Some("All about us"));
+        assert_eq!(metadata.robots(), Some("noindex, nofollow"));
+        assert_eq!(metadata.headings_at(1).collect::<Vec<_>>(), ["Welcome"]);
+        assert_eq!(
+            metadata.headings_at(2).collect::<Vec<_>>(),
+            ["About", "Contact"]
+        );
+        assert_eq!(metadata.headings()[2], (3, "Team".to_owned()));
+        assert_eq!(metadata.canonical(), Some("https://test.com/home"));
+        assert_eq!(
+            metadata.hreflang(),
+            [
+                ("fr".to_owned(), "https://test.com/fr/".to_owned()),
+                ("x-default".to_owned(), "https://test.com/".to_owned()),
+            ]
+        );
+        assert_eq!(metadata.open_graph().len(), 3);
+        assert_eq!(
+            metadata.open_graph()[2],
+            ("og:image".to_owned(), "/b.png".to_owned())
+        );
+        assert_eq!(
+            metadata.twitter(),
+            [("twitter:card".to_owned(), "summary".to_owned())]
+        );
+        assert_eq!(metadata.json_ld(), ["{\"@type\": \"Organization\"}"]);
+
+        let empty = PageMetadata::from_document(&Document::from(""), "https://test.com/");
+        assert_eq!(empty, PageMetadata::default());
+    }
+}
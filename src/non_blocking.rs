@@ -1,47 +1,34 @@
-use super::{Page, SimpleCrawler};
-use anyhow::{Context, Result};
-
-impl SimpleCrawler {
-    async fn request_body(self) -> Result<Self> {
-        let mut new = self;
-
-        for (url, page) in new.urls.iter_mut() {
-            if !page.crawled {
-                let body = reqwest::get(url)
-                    .await
-                    .with_context(|| format!("Request failed GET request for {}", url))?
-                    .text()
-                    .await
-                    .with_context(|| format!("Request failed to extract text for {}", url))?;
-                *page = Page::with_values(&body, &true);
-            }
-        }
-
-        Ok(new)
-    }
+use super::checkpoint::Checkpoint;
+use super::frontier::Workers;
+use super::limits::Budget;
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::{fetch, CrawlError, SimpleCrawler};
 
+impl<S: CrawlStore> SimpleCrawler<S> {
     /// asynchronous crawl gives better performance than it's `crawl_blocking` counterpart. I recommend this
     /// be used in it's place. Example use `let simple_crawler = SimpleCrawler::new().url(&str).crawl()`
-    pub async fn crawl(self) -> Result<Self> {
-        let mut new = self;
+    /// Urls are requested one at a time and urls which can't be fetched are marked as failed and
+    /// the crawl carries on.
+    pub async fn crawl(self) -> Result<Self, CrawlError> {
+        let client = fetch::client(&self.config)?;
+        let budget = Budget::new(&self.config);
+        let checkpoint = Checkpoint::from_config(&self.config)?;
+        let hosts = HostLimiter::new(&self);
+        let new = self
+            .load_robots(&client, &hosts)
+            .await
+            .load_sitemaps(&client, &hosts)
+            .await?;
 
-        // TODO need to better manage stack usage at this point.
-        while new
-            .to_owned()
-            .urls
-            .iter()
-            .filter(|(_, page)| page.crawled == false)
-            .count()
-            > 0
-        {
-            new = new
-                .to_owned()
-                .request_body()
-                .await
-                .with_context(|| format!("Crawl failed with request"))?
-                .get_urls()
-                .with_context(|| format!("Crawl failed with getting urls"))?;
-        }
-        Ok(new)
+        new.crawl_frontier(
+            &client,
+            &budget,
+            &hosts,
+            Workers::Concurrent(1),
+            checkpoint,
+            None,
+        )
+        .await
     }
 }
@@ -0,0 +1,321 @@
+use super::fetch::ResponseInfo;
+use super::store::CrawlStore;
+use super::{CrawlError, SimpleCrawler};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// The Politeness struct sets how hard a crawl works each host. Every request to a host waits
+/// for the longest of the rate, the minimum delay and the Crawl-delay of the host's own robots.txt
+/// since the last one started. For example
+/// `let simple_crawler = SimpleCrawler::builder().politeness(Politeness::new().requests_per_second(2.0)).build()`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Politeness {
+    requests_per_second: Option<f64>,
+    min_delay: Duration,
+    max_in_flight_per_host: Option<usize>,
+    obey_crawl_delay: bool,
+    adaptive_backoff: bool,
+    max_backoff: Duration,
+}
+
+impl Default for Politeness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Politeness {
+    /// No rate or delay is set but the robots.txt Crawl-delay is obeyed and hosts answering 429
+    /// or 503 are backed off from for up to a minute.
+    pub fn new() -> Self {
+        Politeness {
+            requests_per_second: None,
+            min_delay: Duration::from_secs(0),
+            max_in_flight_per_host: None,
+            obey_crawl_delay: true,
+            adaptive_backoff: true,
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// The most requests started each second against a single host.
+    pub fn requests_per_second(self, requests_per_second: f64) -> Self {
+        let mut new = self;
+        new.requests_per_second = Some(requests_per_second).filter(|rps| *rps > 0.0);
+        new
+    }
+
+    /// The shortest time between starting two requests against a single host.
+    pub fn min_delay(self, min_delay: Duration) -> Self {
+        let mut new = self;
+        new.min_delay = min_delay;
+        new
+    }
+
+    /// The most requests in flight against a single host at once whatever the crawl's
+    /// concurrency. At least one request is always allowed.
+    pub fn max_in_flight_per_host(self, max_in_flight: usize) -> Self {
+        let mut new = self;
+        new.max_in_flight_per_host = Some(max_in_flight.max(1));
+        new
+    }
+
+    /// Whether the robots.txt Crawl-delay for the crawler's user agent is waited between requests.
+    pub fn obey_crawl_delay(self, obey_crawl_delay: bool) -> Self {
+        let mut new = self;
+        new.obey_crawl_delay = obey_crawl_delay;
+        new
+    }
+
+    /// Whether a host answering 429 or 503 is backed off from. The `Retry-After` header is waited
+    /// for when it's sent, otherwise the wait doubles each time up to `max_backoff` and halves
+    /// again with each successful response.
+    pub fn adaptive_backoff(self, adaptive_backoff: bool) -> Self {
+        let mut new = self;
+        new.adaptive_backoff = adaptive_backoff;
+        new
+    }
+
+    /// The longest a host is backed off from, including any `Retry-After` it sends.
+    pub fn max_backoff(self, max_backoff: Duration) -> Self {
+        let mut new = self;
+        new.max_backoff = max_backoff;
+        new
+    }
+
+    // The time between starting requests against a host.
+    fn interval(&self, crawl_delay: Option<Duration>) -> Duration {
+        let rate = self
+            .requests_per_second
+            .map(|rps| Duration::from_secs_f64(1.0 / rps))
+            .unwrap_or_default();
+        let crawl_delay = crawl_delay.filter(|_| self.obey_crawl_delay);
+        rate.max(self.min_delay)
+            .max(crawl_delay.unwrap_or_default())
+    }
+}
+
+#[derive(Debug)]
+struct HostState {
+    next_start: Instant,
+    in_flight: usize,
+    backoff: Duration,
+}
+
+/// Keeps track of when each host can next be requested. It's shared between every request so
+/// concurrent and parallel requests wait on each other.
+#[derive(Debug)]
+pub(crate) struct HostLimiter {
+    politeness: Politeness,
+    crawl_delays: Mutex<HashMap<String, Duration>>,
+    hosts: Mutex<HashMap<String, HostState>>,
+    released: Notify,
+}
+
+impl HostLimiter {
+    /// Creates the limiter with the Crawl-delay of every robots.txt the crawler has read so far.
+    pub(crate) fn new<S: CrawlStore>(crawler: &SimpleCrawler<S>) -> Arc<Self> {
+        let user_agent = &crawler.config.user_agent;
+        let crawl_delays = crawler
+            .robots
+            .iter()
+            .filter_map(|(origin, robots)| Some((host(origin)?, robots.crawl_delay(user_agent)?)))
+            .collect();
+
+        Arc::new(HostLimiter {
+            politeness: crawler.config.politeness.to_owned(),
+            crawl_delays: Mutex::new(crawl_delays),
+            hosts: Mutex::new(HashMap::new()),
+            released: Notify::new(),
+        })
+    }
+
+    /// Sets the robots.txt Crawl-delay of a url's host once its robots.txt has been read.
+    pub(crate) fn set_crawl_delay(&self, url: &str, crawl_delay: Option<Duration>) {
+        let (host, delay) = match (host(url), crawl_delay) {
+            (Some(host), Some(delay)) => (host, delay),
+            _ => return,
+        };
+        let mut crawl_delays = self.crawl_delays.lock().unwrap_or_else(|e| e.into_inner());
+        crawl_delays.insert(host, delay);
+    }
+
+    /// Waits until the url's host can be requested.
+    pub(crate) async fn acquire(self: &Arc<Self>, url: &str) -> HostPermit {
+        let host = host(url).unwrap_or_default();
+        loop {
+            // Created before checking so a release in between isn't missed.
+            let released = self.released.notified();
+            match self.try_start(&host) {
+                Ok(()) => return self.permit(host),
+                Err(Some(wait)) => tokio::time::sleep(wait).await,
+                Err(None) => released.await,
+            }
+        }
+    }
+
+    /// Blocks until the url's host can be requested.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn acquire_blocking(self: &Arc<Self>, url: &str) -> HostPermit {
+        let host = host(url).unwrap_or_default();
+        loop {
+            match self.try_start(&host) {
+                Ok(()) => return self.permit(host),
+                Err(wait) => std::thread::sleep(wait.unwrap_or(Duration::from_millis(10))),
+            }
+        }
+    }
+
+    /// Backs off from a host which answered 429 or 503 and eases off again once it answers
+    /// normally.
+    pub(crate) fn record(&self, url: &str, result: &Result<(String, ResponseInfo), CrawlError>) {
+        let response = match result {
+            Ok((_, response)) if self.politeness.adaptive_backoff => response,
+            _ => return,
+        };
+        let host = host(url).unwrap_or_default();
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let state = match hosts.get_mut(&host) {
+            Some(state) => state,
+            None => return,
+        };
+
+        if response.status == 429 || response.status == 503 {
+            let max = self.politeness.max_backoff;
+            state.backoff = (state.backoff * 2).max(Duration::from_secs(1)).min(max);
+            let wait = response.retry_after.unwrap_or(state.backoff).min(max);
+            state.next_start = state.next_start.max(Instant::now() + wait);
+        } else {
+            state.backoff /= 2;
+            if state.backoff < Duration::from_millis(100) {
+                state.backoff = Duration::from_secs(0);
+            }
+        }
+    }
+
+    // Starts a request against the host if it's allowed now. Otherwise returns how long to wait
+    // or `None` if a request in flight needs to finish first.
+    fn try_start(&self, host: &str) -> Result<(), Option<Duration>> {
+        let crawl_delay = self
+            .crawl_delays
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(host)
+            .copied();
+        let interval = self.politeness.interval(crawl_delay);
+        let now = Instant::now();
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let state = hosts.entry(host.to_owned()).or_insert(HostState {
+            next_start: now,
+            in_flight: 0,
+            backoff: Duration::from_secs(0),
+        });
+
+        if let Some(max) = self.politeness.max_in_flight_per_host {
+            if state.in_flight >= max {
+                return Err(None);
+            }
+        }
+        if state.next_start > now {
+            return Err(Some(state.next_start - now));
+        }
+        state.in_flight += 1;
+        state.next_start = now + interval + state.backoff;
+        Ok(())
+    }
+
+    fn permit(self: &Arc<Self>, host: String) -> HostPermit {
+        HostPermit {
+            limiter: self.clone(),
+            host,
+        }
+    }
+}
+
+/// A request in flight against a host. The host's slot is freed when it's dropped.
+#[derive(Debug)]
+pub(crate) struct HostPermit {
+    limiter: Arc<HostLimiter>,
+    host: String,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        let mut hosts = self.limiter.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(state) = hosts.get_mut(&self.host) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+        drop(hosts);
+        self.limiter.released.notify_waiters();
+    }
+}
+
+// Hosts are keyed with their port so two servers on one machine are limited separately.
+fn host(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port_or_known_default() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HostLimiter, Politeness};
+    use crate::{Robots, SimpleCrawler};
+    use std::time::Duration;
+
+    #[test]
+    fn interval_test() {
+        let politeness = Politeness::new().requests_per_second(4.0);
+        assert_eq!(politeness.interval(None), Duration::from_millis(250));
+        assert_eq!(
+            politeness.interval(Some(Duration::from_secs(1))),
+            Duration::from_secs(1)
+        );
+
+        let politeness = politeness
+            .min_delay(Duration::from_millis(500))
+            .obey_crawl_delay(false);
+        assert_eq!(
+            politeness.interval(Some(Duration::from_secs(1))),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn host_limiter_test() {
+        let mut simple_crawler = SimpleCrawler::builder()
+            .politeness(Politeness::new().max_in_flight_per_host(1))
+            .build()
+            .url("https://test.com")
+            .unwrap();
+        simple_crawler.robots.insert(
+            "https://test.com".to_owned(),
+            Robots::parse("User-agent: *\nCrawl-delay: 5"),
+        );
+        let limiter = HostLimiter::new(&simple_crawler);
+
+        // Only one request at a time and the next one waits for the Crawl-delay.
+        assert_eq!(limiter.try_start("test.com:443"), Ok(()));
+        assert_eq!(limiter.try_start("test.com:443"), Err(None));
+        drop(limiter.permit("test.com:443".to_owned()));
+        match limiter.try_start("test.com:443") {
+            Err(Some(wait)) => assert!(wait > Duration::from_secs(4)),
+            other => panic!("expected to wait, got {:?}", other),
+        }
+
+        // Other hosts don't wait until their own robots.txt is read.
+        assert_eq!(limiter.try_start("other.com:443"), Ok(()));
+        drop(limiter.permit("other.com:443".to_owned()));
+        limiter.set_crawl_delay("https://other.com", Some(Duration::from_secs(5)));
+        assert_eq!(limiter.try_start("other.com:443"), Ok(()));
+        drop(limiter.permit("other.com:443".to_owned()));
+        assert!(matches!(limiter.try_start("other.com:443"), Err(Some(_))));
+    }
+}
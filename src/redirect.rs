@@ -0,0 +1,292 @@
+use super::fetch::ResponseInfo;
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::{utils, CrawlError, Page, PageState, SimpleCrawler, SkipReason};
+use reqwest::Client;
+use std::sync::Arc;
+
+/// The RedirectPolicy struct sets how a crawl follows redirects. Every hop is requested by the
+/// crawl itself so it's recorded on the page and waits its turn like any other request. A hop
+/// isn't requested if robots.txt disallows it or the crawl has already fetched or queued it, and
+/// the page keeps the redirect as its response. For example
+/// `let simple_crawler = SimpleCrawler::builder().redirect_policy(RedirectPolicy::new().max_hops(5)).build()`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedirectPolicy {
+    max_hops: u32,
+    follow_off_scope: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RedirectPolicy {
+    /// Up to ten redirects are followed and only to urls in the crawl's `ScopePolicy`.
+    pub fn new() -> Self {
+        RedirectPolicy {
+            max_hops: 10,
+            follow_off_scope: false,
+        }
+    }
+
+    /// How many redirects are followed for one url. A url which redirects more times than this
+    /// fails with `CrawlError::TooManyRedirects`.
+    pub fn max_hops(self, max_hops: u32) -> Self {
+        let mut new = self;
+        new.max_hops = max_hops;
+        new
+    }
+
+    /// Whether redirects to urls outside the crawl's `ScopePolicy` are followed. When they aren't
+    /// the page is kept with the redirect as its response. This is off by default.
+    pub fn follow_off_scope(self, follow: bool) -> Self {
+        let mut new = self;
+        new.follow_off_scope = follow;
+        new
+    }
+
+    pub(crate) fn hops(&self) -> u32 {
+        self.max_hops
+    }
+
+    pub(crate) fn follows_off_scope(&self) -> bool {
+        self.follow_off_scope
+    }
+}
+
+/// One hop of a redirect chain.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Redirect {
+    pub(crate) url: String,
+    pub(crate) status: u16,
+    pub(crate) location: String,
+}
+
+impl Redirect {
+    /// The url which responded with the redirect.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The redirect's status, for example 301.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The Location header resolved against `url`.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+/// The RedirectReport struct lists the redirect chains a crawl found which should be fixed.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedirectReport {
+    loops: Vec<String>,
+    long_chains: Vec<String>,
+}
+
+impl RedirectReport {
+    /// Urls whose redirects lead back to a url already in their chain.
+    pub fn loops(&self) -> &[String] {
+        &self.loops
+    }
+
+    /// Urls which took more than one redirect to reach where they ended up, including chains
+    /// which went over `RedirectPolicy::max_hops`. Links to them are better pointed straight at
+    /// the end of the chain. Loops are left out.
+    pub fn long_chains(&self) -> &[String] {
+        &self.long_chains
+    }
+}
+
+// Whether the last hop of a chain points back at a url already in it.
+pub(crate) fn is_loop(redirects: &[Redirect]) -> bool {
+    redirects
+        .last()
+        .is_some_and(|last| redirects.iter().any(|hop| hop.url == last.location))
+}
+
+/// Why a response which stopped on a redirect failed. Redirects which weren't followed because
+/// they left the crawl's scope aren't failures.
+pub(crate) fn redirect_error(
+    url: &str,
+    response: &ResponseInfo,
+    policy: &RedirectPolicy,
+) -> Option<CrawlError> {
+    let stopped = response
+        .redirects
+        .last()
+        .is_some_and(|last| last.url == response.final_url);
+    if !stopped {
+        None
+    } else if is_loop(&response.redirects) {
+        Some(CrawlError::RedirectLoop {
+            url: url.to_owned(),
+        })
+    } else if response.redirects.len() > policy.max_hops as usize {
+        Some(CrawlError::TooManyRedirects {
+            url: url.to_owned(),
+            max_hops: policy.max_hops,
+        })
+    } else {
+        None
+    }
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// Finds the redirect loops and chains of more than one hop the crawl requested. Both lists
+    /// are sorted.
+    pub fn redirect_report(&self) -> Result<RedirectReport, CrawlError> {
+        let mut loops = vec![];
+        let mut long_chains = vec![];
+        for page in self.urls.iter() {
+            let (url, page) = page?;
+            if is_loop(page.redirects()) {
+                loops.push(url);
+            } else if page.redirects().len() > 1 {
+                long_chains.push(url);
+            }
+        }
+        loops.sort();
+        long_chains.sort();
+
+        Ok(RedirectReport { loops, long_chains })
+    }
+
+    /// Whether the page at `source` should follow its redirect to `hop`. The robots.txt of the
+    /// hop's origin is read if it hasn't been yet.
+    pub(crate) async fn follow_redirect(
+        &mut self,
+        client: &Client,
+        hosts: &Arc<HostLimiter>,
+        source: &str,
+        hop: &str,
+    ) -> Result<bool, CrawlError> {
+        self.load_robots_for(client, hop, hosts).await;
+        self.check_redirect(source, hop)
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn follow_redirect_blocking(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        hosts: &Arc<HostLimiter>,
+        source: &str,
+        hop: &str,
+    ) -> Result<bool, CrawlError> {
+        self.load_robots_for_blocking(client, hop, hosts);
+        self.check_redirect(source, hop)
+    }
+
+    // A hop robots.txt disallows isn't requested and if it's in scope it's skipped. A hop to a url
+    // which has been fetched or is waiting to be isn't requested either so it's only fetched once.
+    fn check_redirect(&mut self, source: &str, hop: &str) -> Result<bool, CrawlError> {
+        let target = utils::normalise_url(&self.base_url, hop, hop, &self.config)
+            .filter(|target| target != source);
+        let allowed = self
+            .robots_for(hop)
+            .is_none_or(|robots| robots.is_allowed(&self.config.user_agent, hop));
+        let target = match (target, allowed) {
+            (Some(target), true) => target,
+            (None, allowed) => return Ok(allowed),
+            (Some(target), false) => {
+                self.skip_redirect_target(source, &target)?;
+                return Ok(false);
+            }
+        };
+        if self.aliases.contains_key(&target) {
+            return Ok(false);
+        }
+        let known = self
+            .urls
+            .get(&target)?
+            .is_some_and(|page| matches!(page.state, PageState::Pending | PageState::Fetched));
+        Ok(!known)
+    }
+
+    // Records a redirect target robots.txt disallows as skipped at the depth of the page which
+    // redirected to it.
+    fn skip_redirect_target(&mut self, source: &str, target: &str) -> Result<(), CrawlError> {
+        let reason = SkipReason::RobotsDisallowed;
+        let depth = self.urls.get(source)?.map(|page| page.depth).unwrap_or(0);
+        let page = Page {
+            depth,
+            ..Page::skipped(reason.to_owned())
+        };
+        if !self.urls.insert_if_absent(target, page)? {
+            self.urls.modify(target, &mut |page| {
+                if page.is_pending() {
+                    page.state = PageState::Skipped(reason.to_owned());
+                }
+            })?;
+        }
+        self.mark_changed(target);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redirect_error, Redirect, RedirectPolicy};
+    use crate::fetch::ResponseInfo;
+    use crate::CrawlError;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn redirect_error_test() {
+        let hop = |url: &str, location: &str| Redirect {
+            url: url.to_owned(),
+            status: 301,
+            location: location.to_owned(),
+        };
+        let response = |final_url: &str, redirects: Vec<Redirect>| ResponseInfo {
+            status: 301,
+            final_url: final_url.to_owned(),
+            headers: vec![],
+            content_type: None,
+            content_length: None,
+            latency: Duration::default(),
+            fetched_at: SystemTime::now(),
+            retry_after: None,
+            robots_tags: vec![],
+            redirects,
+        };
+        let policy = RedirectPolicy::new().max_hops(2);
+        let url = "https://test.com/a";
+
+        // A chain which reached a page and one which stopped at the edge of the crawl's scope.
+        let followed = response("https://test.com/c", vec![hop(url, "https://test.com/c")]);
+        assert_eq!(redirect_error(url, &followed, &policy), None);
+        let off_scope = response(url, vec![hop(url, "https://other.com/")]);
+        assert_eq!(redirect_error(url, &off_scope, &policy), None);
+
+        let looped = vec![
+            hop(url, "https://test.com/b"),
+            hop("https://test.com/b", url),
+        ];
+        assert_eq!(
+            redirect_error(url, &response("https://test.com/b", looped), &policy),
+            Some(CrawlError::RedirectLoop {
+                url: url.to_owned()
+            })
+        );
+        let long = vec![
+            hop(url, "https://test.com/b"),
+            hop("https://test.com/b", "https://test.com/c"),
+            hop("https://test.com/c", "https://test.com/d"),
+        ];
+        assert_eq!(
+            redirect_error(url, &response("https://test.com/c", long), &policy),
+            Some(CrawlError::TooManyRedirects {
+                url: url.to_owned(),
+                max_hops: 2
+            })
+        );
+    }
+}
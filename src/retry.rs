@@ -0,0 +1,86 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// The RetryPolicy struct sets how failed requests are retried. Only connection failures, timeouts
+/// and 5xx or 429 responses are retried. For example
+/// `let simple_crawler = SimpleCrawler::builder().retry_policy(RetryPolicy::new().max_attempts(5)).build()`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Three attempts backing off from half a second up to ten seconds.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// A policy which never retries.
+    pub fn none() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    /// How many times a url is requested in total before it is marked as failed. At least one
+    /// attempt is always made.
+    pub fn max_attempts(self, max_attempts: u32) -> Self {
+        let mut new = self;
+        new.max_attempts = max_attempts.max(1);
+        new
+    }
+
+    /// The wait before the first retry which doubles for each retry after it up to `max`.
+    pub fn backoff(self, initial: Duration, max: Duration) -> Self {
+        let mut new = self;
+        new.initial_backoff = initial;
+        new.max_backoff = max.max(initial);
+        new
+    }
+
+    pub(crate) fn attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    // The wait before the given retry. Half is fixed and half random so that requests which
+    // failed together don't all retry together.
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let exp = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        let half = exp / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn retry_delay_test() {
+        let policy = RetryPolicy::new().backoff(Duration::from_secs(1), Duration::from_secs(4));
+
+        for (retry, max) in [(1, 1), (2, 2), (3, 4), (4, 4), (40, 4)].iter() {
+            let delay = policy.delay(*retry);
+            assert!(delay >= Duration::from_secs(*max) / 2);
+            assert!(delay <= Duration::from_secs(*max));
+        }
+        assert_eq!(RetryPolicy::none().attempts(), 1);
+        assert_eq!(RetryPolicy::new().max_attempts(0).attempts(), 1);
+    }
+}
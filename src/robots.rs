@@ -0,0 +1,466 @@
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::{directives, fetch, utils, CrawlError, PageState, SimpleCrawler, SkipReason};
+use reqwest::{Client, Response, Url};
+use std::sync::Arc;
+use std::time::Duration;
+
+// RFC 9309 asks crawlers to read at least 500 KiB of a robots.txt. Anything after that is ignored.
+const MAX_ROBOTS_SIZE: u64 = 500 * 1024;
+
+/// The Robots struct holds the parsed robots.txt of one origin. Each origin's robots.txt is fetched
+/// the first time one of its urls is about to be requested and cached on the `SimpleCrawler` so
+/// every crawl mode checks urls against the rules of the host they're on.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Robots {
+    groups: Vec<Group>,
+    sitemaps: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    /// Rules which allow every url. Used when the site has no robots.txt.
+    pub fn allow_all() -> Self {
+        Robots {
+            groups: vec![],
+            sitemaps: vec![],
+        }
+    }
+
+    /// Rules which disallow every url. Used when robots.txt can't be fetched because of a server
+    /// error as the site may not want to be crawled at all.
+    pub fn disallow_all() -> Self {
+        Robots {
+            groups: vec![Group {
+                user_agents: vec!["*".to_owned()],
+                rules: vec![Rule {
+                    allow: false,
+                    pattern: "/".to_owned(),
+                }],
+                crawl_delay: None,
+            }],
+            sitemaps: vec![],
+        }
+    }
+
+    /// Parse the text of a robots.txt file. Unknown lines are ignored and so are relative Sitemap
+    /// entries as there's nothing to resolve them against.
+    pub fn parse(txt: &str) -> Self {
+        Robots::parse_with_base(None, txt)
+    }
+
+    /// Parse the text of the robots.txt file at `robots_url`. Relative Sitemap entries are
+    /// resolved against it and entries which don't resolve are ignored.
+    pub fn parse_for(robots_url: &str, txt: &str) -> Self {
+        match Url::parse(robots_url) {
+            Ok(base) => Robots::parse_with_base(Some(&base), txt),
+            Err(_) => Robots::parse(txt),
+        }
+    }
+
+    fn parse_with_base(base: Option<&Url>, txt: &str) -> Self {
+        let mut groups: Vec<Group> = vec![];
+        let mut sitemaps = vec![];
+        // A user-agent line following rules starts a new group.
+        let mut in_rules = true;
+
+        for line in txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (key, value) = match line.find(':') {
+                Some(i) => (line[..i].trim().to_lowercase(), line[i + 1..].trim()),
+                None => continue,
+            };
+
+            match key.as_ref() {
+                "user-agent" => {
+                    if in_rules || groups.is_empty() {
+                        groups.push(Group {
+                            user_agents: vec![],
+                            rules: vec![],
+                            crawl_delay: None,
+                        });
+                        in_rules = false;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.user_agents.push(value.to_lowercase());
+                    }
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    // An empty disallow means allow everything so it has no effect.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_owned(),
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    in_rules = true;
+                    if let (Some(group), Ok(delay)) = (groups.last_mut(), value.parse::<f64>()) {
+                        if delay.is_finite() && delay >= 0.0 {
+                            group.crawl_delay = Some(Duration::from_secs_f64(delay));
+                        }
+                    }
+                }
+                "sitemap" if !value.is_empty() => {
+                    let sitemap = match base {
+                        Some(base) => base.join(value),
+                        None => Url::parse(value),
+                    };
+                    if let Ok(sitemap) = sitemap {
+                        sitemaps.push(sitemap.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Robots { groups, sitemaps }
+    }
+
+    /// Builds the rules from a robots.txt response. A missing file allows everything and a server
+    /// error disallows everything.
+    fn from_response(robots_url: &str, status: u16, body: &str) -> Self {
+        match status {
+            200..=299 => Robots::parse_for(robots_url, body),
+            500..=599 => Robots::disallow_all(),
+            _ => Robots::allow_all(),
+        }
+    }
+
+    /// Returns true if the user agent is allowed to fetch the url.
+    pub fn is_allowed(&self, user_agent: &str, url: &str) -> bool {
+        let path = match Url::parse(url) {
+            Ok(u) => match u.query() {
+                Some(q) => format!("{}?{}", u.path(), q),
+                None => u.path().to_owned(),
+            },
+            Err(_) => url.to_owned(),
+        };
+        if path == "/robots.txt" {
+            return true;
+        }
+
+        // The longest matching pattern wins and allow wins a tie.
+        let mut best: Option<&Rule> = None;
+        for rule in self
+            .groups_for(user_agent)
+            .iter()
+            .flat_map(|g| g.rules.iter())
+        {
+            if !pattern_matches(&rule.pattern, &path) {
+                continue;
+            }
+            best = match best {
+                Some(b)
+                    if b.pattern.len() > rule.pattern.len()
+                        || (b.pattern.len() == rule.pattern.len() && b.allow) =>
+                {
+                    Some(b)
+                }
+                _ => Some(rule),
+            };
+        }
+
+        best.map(|r| r.allow).unwrap_or(true)
+    }
+
+    /// The Crawl-delay for the user agent if one is set.
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.groups_for(user_agent)
+            .iter()
+            .filter_map(|g| g.crawl_delay)
+            .max()
+    }
+
+    /// Sitemap urls listed in robots.txt.
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+
+    // The groups whose user-agent is the product token of the user agent, falling back to the
+    // `*` groups. Tokens are matched exactly ignoring case as RFC 9309 asks.
+    fn groups_for(&self, user_agent: &str) -> Vec<&Group> {
+        let token = directives::product_token(user_agent);
+        let best = match self.groups.iter().any(|g| g.user_agents.contains(&token)) {
+            true => token.as_str(),
+            false => "*",
+        };
+
+        self.groups
+            .iter()
+            .filter(|g| g.user_agents.iter().any(|ua| ua == best))
+            .collect()
+    }
+}
+
+// Match a robots.txt path pattern supporting `*` wildcards and a `$` end anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    let mut rest = match path.strip_prefix(parts[0]) {
+        Some(r) => r,
+        None => return false,
+    };
+    if parts.len() == 1 {
+        return !anchored || rest.is_empty();
+    }
+
+    for (i, part) in parts[1..].iter().enumerate() {
+        if anchored && i == parts.len() - 2 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+// Reads the first `MAX_ROBOTS_SIZE` bytes of a robots.txt response so a huge or endless one is
+// never held in full.
+async fn read_robots(resp: Response) -> Option<String> {
+    let mut resp = resp;
+    let mut bytes = vec![];
+    while let Some(chunk) = resp.chunk().await.ok()? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 >= MAX_ROBOTS_SIZE {
+            bytes.truncate(MAX_ROBOTS_SIZE as usize);
+            break;
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(feature = "blocking")]
+fn read_robots_blocking(resp: reqwest::blocking::Response) -> Option<String> {
+    use std::io::Read;
+
+    let mut bytes = vec![];
+    resp.take(MAX_ROBOTS_SIZE).read_to_end(&mut bytes).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// The robots.txt rules for the base url's origin. This is `None` until a crawl has been
+    /// started.
+    pub fn robots(&self) -> Option<&Robots> {
+        self.robots_for(&self.base_url)
+    }
+
+    /// The robots.txt rules for a url's origin. Each origin's robots.txt is read the first time
+    /// one of its urls is about to be requested so this is `None` until then.
+    pub fn robots_for(&self, url: &str) -> Option<&Robots> {
+        self.robots.get(&utils::origin(url)?)
+    }
+
+    /// Reads the robots.txt of the base url's origin before the crawl starts so its sitemaps are
+    /// known.
+    pub(crate) async fn load_robots(self, client: &Client, hosts: &Arc<HostLimiter>) -> Self {
+        let mut new = self;
+        let base_url = new.base_url.to_owned();
+        new.load_robots_for(client, &base_url, hosts).await;
+        new
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn load_robots_blocking(
+        self,
+        client: &reqwest::blocking::Client,
+        hosts: &Arc<HostLimiter>,
+    ) -> Self {
+        let mut new = self;
+        let base_url = new.base_url.to_owned();
+        new.load_robots_for_blocking(client, &base_url, hosts);
+        new
+    }
+
+    /// Reads and caches the robots.txt of a url's origin if it hasn't been read yet and hands its
+    /// Crawl-delay to `hosts`.
+    pub(crate) async fn load_robots_for(
+        &mut self,
+        client: &Client,
+        url: &str,
+        hosts: &Arc<HostLimiter>,
+    ) {
+        let origin = match utils::origin(url) {
+            Some(origin) if !self.robots.contains_key(&origin) => origin,
+            _ => return,
+        };
+        let robots = match utils::robots_url(&origin) {
+            Some(robots_url) => {
+                let permit = hosts.acquire(&robots_url).await;
+                let robots = match fetch::get(client, &robots_url).await {
+                    Ok(resp) => {
+                        let status = resp.status().as_u16();
+                        match read_robots(resp).await {
+                            Some(body) => Robots::from_response(&robots_url, status, &body),
+                            None => Robots::allow_all(),
+                        }
+                    }
+                    // If the host can't be reached the crawl itself will record it.
+                    Err(_) => Robots::allow_all(),
+                };
+                drop(permit);
+                robots
+            }
+            None => Robots::allow_all(),
+        };
+        self.cache_robots(origin, robots, hosts);
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn load_robots_for_blocking(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        url: &str,
+        hosts: &Arc<HostLimiter>,
+    ) {
+        let origin = match utils::origin(url) {
+            Some(origin) if !self.robots.contains_key(&origin) => origin,
+            _ => return,
+        };
+        let robots = match utils::robots_url(&origin) {
+            Some(robots_url) => {
+                let permit = hosts.acquire_blocking(&robots_url);
+                let robots = match fetch::get_blocking(client, &robots_url) {
+                    Ok(resp) => {
+                        let status = resp.status().as_u16();
+                        match read_robots_blocking(resp) {
+                            Some(body) => Robots::from_response(&robots_url, status, &body),
+                            None => Robots::allow_all(),
+                        }
+                    }
+                    Err(_) => Robots::allow_all(),
+                };
+                drop(permit);
+                robots
+            }
+            None => Robots::allow_all(),
+        };
+        self.cache_robots(origin, robots, hosts);
+    }
+
+    fn cache_robots(&mut self, origin: String, robots: Robots, hosts: &HostLimiter) {
+        hosts.set_crawl_delay(&origin, robots.crawl_delay(&self.config.user_agent));
+        self.robots.insert(origin, robots);
+    }
+
+    /// Marks a pending url as skipped if the robots.txt of its origin disallows it so it's never
+    /// requested. Returns true if the url was skipped.
+    pub(crate) fn skip_if_disallowed(&mut self, url: &str) -> Result<bool, CrawlError> {
+        let allowed = match self.robots_for(url) {
+            Some(robots) => robots.is_allowed(&self.config.user_agent, url),
+            None => true,
+        };
+        if !allowed {
+            self.urls
+                .mark_state(url, PageState::Skipped(SkipReason::RobotsDisallowed))?;
+            self.mark_changed(url);
+        }
+        Ok(!allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Robots;
+    use std::time::Duration;
+
+    const ROBOTS: &str = "\
+# comment
+User-agent: *
+Disallow: /private
+Allow: /private/public
+Disallow: /*.pdf$
+Crawl-delay: 2
+
+User-agent: simple_crawler
+User-agent: other
+Disallow: /blocked/
+Crawl-delay: 0.5
+
+Sitemap: https://test.com/sitemap.xml
+";
+
+    #[test]
+    fn robots_groups_test() {
+        let robots = Robots::parse(ROBOTS);
+
+        // The user agent's own group is used and the * group ignored.
+        assert!(!robots.is_allowed("simple_crawler/0.1", "https://test.com/blocked/a"));
+        assert!(robots.is_allowed("simple_crawler/0.1", "https://test.com/private"));
+        assert_eq!(
+            robots.crawl_delay("simple_crawler/0.1"),
+            Some(Duration::from_millis(500))
+        );
+
+        assert!(!robots.is_allowed("another", "https://test.com/private/a"));
+        assert_eq!(robots.crawl_delay("another"), Some(Duration::from_secs(2)));
+        assert_eq!(robots.sitemaps(), ["https://test.com/sitemap.xml"]);
+
+        // A group only applies to the whole product token.
+        let robots =
+            Robots::parse("User-agent: s\nDisallow: /\n\nUser-agent: Simple_Crawler\nDisallow: /a");
+        assert!(robots.is_allowed("simple_crawler/0.1", "https://test.com/b"));
+        assert!(!robots.is_allowed("Simple_Crawler", "https://test.com/a"));
+        assert!(!robots.is_allowed("s/1.0", "https://test.com/b"));
+    }
+
+    #[test]
+    fn robots_sitemaps_test() {
+        let txt =
+            "Sitemap: /a.xml\nSitemap: b.xml\nSitemap: https://other.com/c.xml\nSitemap: http://[";
+        let robots = Robots::parse_for("https://test.com/robots.txt", txt);
+        assert_eq!(
+            robots.sitemaps(),
+            [
+                "https://test.com/a.xml",
+                "https://test.com/b.xml",
+                "https://other.com/c.xml"
+            ]
+        );
+
+        // Without the robots.txt url only absolute entries are kept.
+        assert_eq!(Robots::parse(txt).sitemaps(), ["https://other.com/c.xml"]);
+    }
+
+    #[test]
+    fn robots_rules_test() {
+        let robots = Robots::parse(ROBOTS);
+
+        // Longest match wins.
+        assert!(robots.is_allowed("another", "https://test.com/private/public/a"));
+        // Wildcards and end anchors.
+        assert!(!robots.is_allowed("another", "https://test.com/a/b.pdf"));
+        assert!(robots.is_allowed("another", "https://test.com/a/b.pdf?x=1"));
+        assert!(robots.is_allowed("another", "https://test.com/robots.txt"));
+
+        assert!(Robots::allow_all().is_allowed("another", "https://test.com/a"));
+        assert!(!Robots::disallow_all().is_allowed("another", "https://test.com/a"));
+    }
+}
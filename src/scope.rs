@@ -0,0 +1,309 @@
+use super::CrawlError;
+use regex::Regex;
+use reqwest::Url;
+
+/// Which hosts a crawl follows links to before any allow or deny lists are applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+enum HostScope {
+    /// Only the base url's host. `www.example.com` and `example.com` are different hosts.
+    SameHost,
+    /// Any host under the base url's registrable domain, so `www.example.com`,
+    /// `blog.example.com` and `example.com` are all crawled. Public suffixes such as `co.uk` or
+    /// `github.io` are taken into account.
+    SameDomain,
+}
+
+/// The ScopePolicy struct sets which urls a crawl follows. A url is in scope if its host is
+/// allowed, it's under the path prefix, it matches an include pattern if any are set and it
+/// matches no exclude pattern. Patterns are matched against the whole url. For example
+/// `let simple_crawler = SimpleCrawler::builder().scope(ScopePolicy::same_domain().path_prefix("/blog")).build()`
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScopePolicy {
+    hosts: HostScope,
+    allow_hosts: Vec<String>,
+    deny_hosts: Vec<String>,
+    path_prefix: Option<String>,
+    #[cfg_attr(feature = "serde", serde(with = "patterns"))]
+    include: Vec<Regex>,
+    #[cfg_attr(feature = "serde", serde(with = "patterns"))]
+    exclude: Vec<Regex>,
+}
+
+impl Default for ScopePolicy {
+    fn default() -> Self {
+        Self::same_host()
+    }
+}
+
+// Regexes can't be compared so two policies are equal if their patterns are the same.
+impl PartialEq for ScopePolicy {
+    fn eq(&self, other: &Self) -> bool {
+        let patterns = |r: &[Regex]| r.iter().map(|r| r.as_str().to_owned()).collect::<Vec<_>>();
+        self.hosts == other.hosts
+            && self.allow_hosts == other.allow_hosts
+            && self.deny_hosts == other.deny_hosts
+            && self.path_prefix == other.path_prefix
+            && patterns(&self.include) == patterns(&other.include)
+            && patterns(&self.exclude) == patterns(&other.exclude)
+    }
+}
+
+impl ScopePolicy {
+    /// Only follow links to the base url's host. This is the default.
+    pub fn same_host() -> Self {
+        ScopePolicy {
+            hosts: HostScope::SameHost,
+            allow_hosts: vec![],
+            deny_hosts: vec![],
+            path_prefix: None,
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+
+    /// Follow links to any host under the base url's registrable domain.
+    pub fn same_domain() -> Self {
+        ScopePolicy {
+            hosts: HostScope::SameDomain,
+            ..Self::same_host()
+        }
+    }
+
+    /// Also follow links to these hosts or their subdomains.
+    pub fn allow_hosts(self, hosts: &[&str]) -> Self {
+        let mut new = self;
+        new.allow_hosts
+            .extend(hosts.iter().map(|h| h.to_lowercase()));
+        new
+    }
+
+    /// Never follow links to these hosts or their subdomains, even if they'd otherwise be in scope.
+    pub fn deny_hosts(self, hosts: &[&str]) -> Self {
+        let mut new = self;
+        new.deny_hosts
+            .extend(hosts.iter().map(|h| h.to_lowercase()));
+        new
+    }
+
+    /// Only follow links whose path is this prefix or under it, for example `/blog` follows
+    /// `/blog` and `/blog/post` but not `/blogger`.
+    pub fn path_prefix(self, prefix: &str) -> Self {
+        let mut new = self;
+        new.path_prefix = Some(prefix.to_owned());
+        new
+    }
+
+    /// Only follow links matching one of the include patterns. Returns an error if the regex is
+    /// invalid.
+    pub fn include(self, pattern: &str) -> Result<Self, CrawlError> {
+        let mut new = self;
+        new.include.push(compile(pattern, pattern)?);
+        Ok(new)
+    }
+
+    /// Never follow links matching this regex. Returns an error if the regex is invalid.
+    pub fn exclude(self, pattern: &str) -> Result<Self, CrawlError> {
+        let mut new = self;
+        new.exclude.push(compile(pattern, pattern)?);
+        Ok(new)
+    }
+
+    /// Like `include` but with a glob where `*` matches within a path segment, `**` matches
+    /// across them and `?` matches a single character, for example `https://*.example.com/**`.
+    pub fn include_glob(self, glob: &str) -> Result<Self, CrawlError> {
+        let mut new = self;
+        new.include.push(compile(glob, &glob_to_regex(glob))?);
+        Ok(new)
+    }
+
+    /// Like `exclude` but with a glob, for example `**/*.pdf`.
+    pub fn exclude_glob(self, glob: &str) -> Result<Self, CrawlError> {
+        let mut new = self;
+        new.exclude.push(compile(glob, &glob_to_regex(glob))?);
+        Ok(new)
+    }
+
+    /// Whether a link to `url` found while crawling `base` should be followed.
+    pub(crate) fn in_scope(&self, base: &Url, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+        let base_host = base.host_str().unwrap_or_default().to_lowercase();
+
+        if self
+            .deny_hosts
+            .iter()
+            .any(|d| is_same_or_subdomain(&host, d))
+        {
+            return false;
+        }
+        let host_allowed = self
+            .allow_hosts
+            .iter()
+            .any(|a| is_same_or_subdomain(&host, a))
+            || match self.hosts {
+                HostScope::SameHost => host == base_host,
+                HostScope::SameDomain => {
+                    host == base_host
+                        || registrable_domain(&base_host)
+                            .is_some_and(|domain| is_same_or_subdomain(&host, &domain))
+                }
+            };
+        if !host_allowed {
+            return false;
+        }
+
+        if let Some(prefix) = &self.path_prefix {
+            if !under_path(url.path(), prefix) {
+                return false;
+            }
+        }
+
+        let url = url.as_str();
+        (self.include.is_empty() || self.include.iter().any(|r| r.is_match(url)))
+            && !self.exclude.iter().any(|r| r.is_match(url))
+    }
+}
+
+fn compile(pattern: &str, regex: &str) -> Result<Regex, CrawlError> {
+    Regex::new(regex).map_err(|source| CrawlError::InvalidPattern {
+        pattern: pattern.to_owned(),
+        source,
+    })
+}
+
+// Globs match the whole url so the regex is anchored at both ends.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = "^".to_owned();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+// Whether a path is the prefix or under it. The prefix only matches whole segments so `/blog`
+// doesn't match `/blogger`.
+fn under_path(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn is_same_or_subdomain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+// The registrable domain is one label more than the public suffix, e.g. example.co.uk. IP
+// addresses and bare public suffixes don't have one.
+fn registrable_domain(host: &str) -> Option<String> {
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return None;
+    }
+    psl::domain_str(host).map(|d| d.to_owned())
+}
+
+// Patterns are serialized as the regexes they were compiled from.
+#[cfg(feature = "serde")]
+mod patterns {
+    use regex::Regex;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(regexes: &[Regex], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(regexes.iter().map(|r| r.as_str()))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Regex>, D::Error> {
+        Vec::<String>::deserialize(d)?
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScopePolicy;
+    use reqwest::Url;
+
+    fn in_scope(policy: &ScopePolicy, url: &str) -> bool {
+        let base = Url::parse("https://www.example.co.uk/").unwrap();
+        policy.in_scope(&base, &Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn host_scope_test() {
+        let policy = ScopePolicy::same_host();
+        assert!(in_scope(&policy, "https://www.example.co.uk/a"));
+        assert!(!in_scope(&policy, "https://example.co.uk/a"));
+
+        let policy = ScopePolicy::same_domain()
+            .allow_hosts(&["cdn.other.com"])
+            .deny_hosts(&["private.example.co.uk"]);
+        assert!(in_scope(&policy, "https://example.co.uk/a"));
+        assert!(in_scope(&policy, "https://blog.example.co.uk/a"));
+        assert!(in_scope(&policy, "https://cdn.other.com/a"));
+        assert!(!in_scope(&policy, "https://other.co.uk/a"));
+        assert!(!in_scope(&policy, "https://private.example.co.uk/a"));
+        assert!(!in_scope(&policy, "https://a.private.example.co.uk/a"));
+
+        // Allowed hosts match their subdomains the same way denied hosts do.
+        assert!(in_scope(&policy, "https://eu.cdn.other.com/a"));
+        assert!(!in_scope(&policy, "https://other.com/a"));
+        assert!(!in_scope(&policy, "https://notcdn.other.com/a"));
+        assert!(!in_scope(&policy, "mailto:someone@example.co.uk"));
+    }
+
+    #[test]
+    fn path_and_pattern_test() -> Result<(), crate::CrawlError> {
+        let policy = ScopePolicy::same_host()
+            .path_prefix("/blog/")
+            .include_glob("https://www.example.co.uk/blog/**")?
+            .exclude_glob("**/*.pdf")?
+            .exclude("/tag/")?;
+        assert!(in_scope(&policy, "https://www.example.co.uk/blog/post"));
+        assert!(!in_scope(&policy, "https://www.example.co.uk/about"));
+        assert!(!in_scope(
+            &policy,
+            "https://www.example.co.uk/blog/post.pdf"
+        ));
+        assert!(!in_scope(
+            &policy,
+            "https://www.example.co.uk/blog/tag/rust"
+        ));
+
+        // The prefix only matches whole path segments.
+        let policy = ScopePolicy::same_host().path_prefix("/blog");
+        assert!(in_scope(&policy, "https://www.example.co.uk/blog"));
+        assert!(in_scope(&policy, "https://www.example.co.uk/blog/post"));
+        assert!(!in_scope(&policy, "https://www.example.co.uk/blogger"));
+        assert!(!in_scope(
+            &policy,
+            "https://www.example.co.uk/blog-old/post"
+        ));
+
+        assert!(ScopePolicy::same_host().include("(").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,526 @@
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::{fetch, utils, CrawlError, Page, SimpleCrawler};
+use flate2::read::GzDecoder;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::{Client, Response};
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+use std::sync::Arc;
+
+// The sitemaps protocol limits a sitemap to 50MB uncompressed.
+const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+// An index can list 50,000 sitemaps which is far more than are worth reading before a crawl.
+const MAX_SITEMAPS: usize = 1000;
+
+/// How often a sitemap says a page is likely to change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFreq {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "always" => Some(ChangeFreq::Always),
+            "hourly" => Some(ChangeFreq::Hourly),
+            "daily" => Some(ChangeFreq::Daily),
+            "weekly" => Some(ChangeFreq::Weekly),
+            "monthly" => Some(ChangeFreq::Monthly),
+            "yearly" => Some(ChangeFreq::Yearly),
+            "never" => Some(ChangeFreq::Never),
+            _ => None,
+        }
+    }
+}
+
+/// The SitemapEntry struct records what a sitemap said about a page.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SitemapEntry {
+    pub(crate) sitemap: String,
+    pub(crate) lastmod: Option<String>,
+    pub(crate) changefreq: Option<ChangeFreq>,
+    pub(crate) priority: Option<f32>,
+}
+
+impl SitemapEntry {
+    fn new(sitemap: &str) -> Self {
+        SitemapEntry {
+            sitemap: sitemap.to_owned(),
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+        }
+    }
+
+    /// The url of the sitemap the page was listed in.
+    pub fn sitemap(&self) -> &str {
+        &self.sitemap
+    }
+
+    /// When the page was last modified as a W3C datetime, for example `2021-03-01` or
+    /// `2021-03-01T12:00:00+00:00`.
+    pub fn lastmod(&self) -> Option<&str> {
+        self.lastmod.as_deref()
+    }
+
+    /// How often the page is likely to change.
+    pub fn changefreq(&self) -> Option<ChangeFreq> {
+        self.changefreq
+    }
+
+    /// The priority of the page relative to others on the site from 0.0 to 1.0.
+    pub fn priority(&self) -> Option<f32> {
+        self.priority
+    }
+}
+
+/// The SitemapReport struct compares a site's sitemaps with the links found while crawling it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SitemapReport {
+    only_in_sitemap: Vec<String>,
+    missing_from_sitemap: Vec<String>,
+}
+
+impl SitemapReport {
+    /// Urls listed in a sitemap which no crawled page links to.
+    pub fn only_in_sitemap(&self) -> &[String] {
+        &self.only_in_sitemap
+    }
+
+    /// Pages which were linked to and fetched successfully but aren't listed in any sitemap.
+    /// Pages which asked not to be indexed or named another canonical url are left out.
+    pub fn missing_from_sitemap(&self) -> &[String] {
+        &self.missing_from_sitemap
+    }
+}
+
+/// The urls and nested sitemaps listed in one sitemap.
+#[derive(Debug, Default, PartialEq)]
+struct Sitemap {
+    sitemaps: Vec<String>,
+    urls: Vec<(String, SitemapEntry)>,
+}
+
+impl Sitemap {
+    /// Parses a sitemap index or urlset. Anything after malformed xml is ignored.
+    fn parse(url: &str, xml: &[u8]) -> Self {
+        let xml = String::from_utf8_lossy(xml);
+        let mut reader = Reader::from_str(&xml);
+        let mut sitemap = Sitemap::default();
+        // The local names of the open elements so fields of extensions such as `<image:loc>`
+        // aren't mistaken for the url's own.
+        let mut open: Vec<String> = vec![];
+        let mut text = String::new();
+        let mut loc = None;
+        let mut entry = SitemapEntry::new(url);
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) => {
+                    let name = e.local_name().as_ref().to_ascii_lowercase();
+                    if name == "url" || name == "sitemap" {
+                        loc = None;
+                        entry = SitemapEntry::new(url);
+                    }
+                    open.push(name);
+                    text.clear();
+                }
+                Ok(Event::Text(t)) => text.push_str(&t.xml10_content()),
+                Ok(Event::CData(t)) => text.push_str(&t),
+                Ok(Event::GeneralRef(r)) => match r.resolve_char_ref() {
+                    Ok(Some(c)) => text.push(c),
+                    _ => text.push_str(resolve_predefined_entity(&r).unwrap_or_default()),
+                },
+                Ok(Event::End(_)) => {
+                    let name = open.pop().unwrap_or_default();
+                    let parent = open.last().map(|p| p.as_str()).unwrap_or_default();
+                    let value = Some(text.trim().to_owned()).filter(|v| !v.is_empty());
+                    match (parent, name.as_str()) {
+                        ("url" | "sitemap", "loc") => loc = value,
+                        ("url", "lastmod") => entry.lastmod = value,
+                        ("url", "changefreq") => {
+                            entry.changefreq = value.as_deref().and_then(ChangeFreq::parse)
+                        }
+                        ("url", "priority") => {
+                            entry.priority = value
+                                .and_then(|v| v.parse().ok())
+                                .filter(|p| (0.0..=1.0).contains(p))
+                        }
+                        (_, "url") => {
+                            if let Some(loc) = loc.take() {
+                                let entry = std::mem::replace(&mut entry, SitemapEntry::new(url));
+                                sitemap.urls.push((loc, entry));
+                            }
+                        }
+                        (_, "sitemap") => sitemap.sitemaps.extend(loc.take()),
+                        _ => {}
+                    }
+                    text.clear();
+                }
+                Ok(Event::Eof) | Err(_) => break,
+                _ => {}
+            }
+        }
+        sitemap
+    }
+}
+
+// Sitemaps may be gzipped whatever their url or Content-Type says so the gzip magic number is
+// checked instead.
+fn decompress(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut xml = vec![];
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(bytes)
+            .take(MAX_SITEMAP_SIZE + 1)
+            .read_to_end(&mut xml)
+            .ok()?;
+    } else {
+        xml.extend_from_slice(bytes);
+    }
+    Some(xml).filter(|xml| xml.len() as u64 <= MAX_SITEMAP_SIZE)
+}
+
+// Reads the body of a successful sitemap response. Reading stops as soon as it's larger than any
+// sitemap can be so a huge or endless response is never held in full.
+async fn read_sitemap(resp: Response) -> Option<Vec<u8>> {
+    if !resp.status().is_success() || resp.content_length().unwrap_or(0) > MAX_SITEMAP_SIZE {
+        return None;
+    }
+    let mut resp = resp;
+    let mut bytes = vec![];
+    while let Some(chunk) = resp.chunk().await.ok()? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 > MAX_SITEMAP_SIZE {
+            return None;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(feature = "blocking")]
+fn read_sitemap_blocking(resp: reqwest::blocking::Response) -> Option<Vec<u8>> {
+    if !resp.status().is_success() || resp.content_length().unwrap_or(0) > MAX_SITEMAP_SIZE {
+        return None;
+    }
+    let mut bytes = vec![];
+    resp.take(MAX_SITEMAP_SIZE + 1)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    Some(bytes).filter(|bytes| bytes.len() as u64 <= MAX_SITEMAP_SIZE)
+}
+
+/// The sitemaps waiting to be read. Each is only read once so indexes listing each other can't
+/// loop.
+struct SitemapQueue {
+    queue: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl SitemapQueue {
+    fn new(urls: Vec<String>) -> Self {
+        let mut new = SitemapQueue {
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+        };
+        for url in urls {
+            new.push(url);
+        }
+        new
+    }
+
+    fn push(&mut self, url: String) {
+        if self.seen.len() < MAX_SITEMAPS && self.seen.insert(url.to_owned()) {
+            self.queue.push_back(url);
+        }
+    }
+
+    fn pop(&mut self) -> Option<String> {
+        self.queue.pop_front()
+    }
+}
+
+impl<S: CrawlStore> SimpleCrawler<S> {
+    /// The sitemaps which were read at the start of the crawl. This is `None` until a crawl has
+    /// been started with `CrawlerBuilder::sitemaps` on.
+    pub fn sitemaps(&self) -> Option<&[String]> {
+        self.sitemaps.as_deref()
+    }
+
+    /// Compares the urls listed in the site's sitemaps with the pages linked to during the crawl.
+    /// Both lists are sorted.
+    pub fn sitemap_report(&self) -> Result<SitemapReport, CrawlError> {
+        let mut only_in_sitemap = vec![];
+        let mut missing_from_sitemap = vec![];
+        for page in self.urls.iter() {
+            let (url, page) = page?;
+            if page.sitemap.is_some() && page.referrers.is_empty() {
+                only_in_sitemap.push(url);
+            } else if page.sitemap.is_none()
+                && !page.referrers.is_empty()
+                && page.status().is_some_and(|s| (200..300).contains(&s))
+                && !page.robots_directives.noindex()
+                && page.canonical_url.is_none()
+            {
+                missing_from_sitemap.push(url);
+            }
+        }
+        only_in_sitemap.sort();
+        missing_from_sitemap.sort();
+
+        Ok(SitemapReport {
+            only_in_sitemap,
+            missing_from_sitemap,
+        })
+    }
+
+    // The sitemaps listed in robots.txt and the one at the conventional location.
+    fn sitemap_queue(&self) -> SitemapQueue {
+        let mut urls = self
+            .robots()
+            .map(|robots| robots.sitemaps().to_vec())
+            .unwrap_or_default();
+        urls.extend(utils::sitemap_url(&self.base_url));
+        SitemapQueue::new(urls)
+    }
+
+    // Adds the urls listed in a sitemap as seeds and queues any sitemaps it lists. Urls outside
+    // the crawl's scope are left out like any link would be.
+    fn add_sitemap(
+        &mut self,
+        url: String,
+        sitemap: Sitemap,
+        queue: &mut SitemapQueue,
+    ) -> Result<(), CrawlError> {
+        for nested in sitemap.sitemaps {
+            if self.sitemap_in_scope(&nested) {
+                queue.push(nested);
+            }
+        }
+        for (loc, entry) in sitemap.urls {
+            if let Some(loc) = utils::normalise_url(&self.base_url, &loc, &loc, &self.config) {
+                let page = Page {
+                    sitemap: Some(entry.to_owned()),
+                    ..Page::new()
+                };
+                if !self.urls.insert_if_absent(&loc, page)? {
+                    self.urls.modify(&loc, &mut |page| {
+                        page.sitemap.get_or_insert_with(|| entry.to_owned());
+                    })?;
+                }
+            }
+        }
+        self.sitemaps.get_or_insert_with(Vec::new).push(url);
+        Ok(())
+    }
+
+    // Whether the robots.txt of a sitemap's origin lets it be read.
+    fn sitemap_allowed(&self, url: &str) -> bool {
+        self.robots_for(url)
+            .is_none_or(|robots| robots.is_allowed(&self.config.user_agent, url))
+    }
+
+    // Whether a sitemap listed in an index or redirected to is inside the crawl's scope.
+    fn sitemap_in_scope(&self, url: &str) -> bool {
+        utils::normalise_url(&self.base_url, url, url, &self.config).is_some()
+    }
+
+    // Whether a sitemap's redirect to `next` is followed. Its chain is checked against the
+    // crawl's `RedirectPolicy` the same way a page's is.
+    fn follows_sitemap_redirect(&self, chain: &[String], next: &str) -> bool {
+        chain.len() <= self.config.redirects.hops() as usize
+            && !chain.iter().any(|url| url == next)
+            && (self.sitemap_in_scope(next) || self.config.redirects.follows_off_scope())
+    }
+
+    // Reads a sitemap one request at a time so each redirect is checked like a page's would be
+    // and waits for its own host. Returns `None` if a request fails or the sitemap redirects
+    // somewhere which isn't followed.
+    async fn get_sitemap(
+        &mut self,
+        client: &Client,
+        url: &str,
+        hosts: &Arc<HostLimiter>,
+    ) -> Option<Vec<u8>> {
+        let mut chain = vec![url.to_owned()];
+        loop {
+            let url = chain.last()?.to_owned();
+            let permit = hosts.acquire(&url).await;
+            let resp = client.get(&url).send().await.ok()?;
+            let next = match fetch::location(resp.status(), resp.url(), resp.headers()) {
+                Some(next) => next.to_string(),
+                None => return read_sitemap(resp).await,
+            };
+            drop(permit);
+            if !self.follows_sitemap_redirect(&chain, &next) {
+                return None;
+            }
+            self.load_robots_for(client, &next, hosts).await;
+            if !self.sitemap_allowed(&next) {
+                return None;
+            }
+            chain.push(next);
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    fn get_sitemap_blocking(
+        &mut self,
+        client: &reqwest::blocking::Client,
+        url: &str,
+        hosts: &Arc<HostLimiter>,
+    ) -> Option<Vec<u8>> {
+        let mut chain = vec![url.to_owned()];
+        loop {
+            let url = chain.last()?.to_owned();
+            let permit = hosts.acquire_blocking(&url);
+            let resp = client.get(&url).send().ok()?;
+            let next = match fetch::location(resp.status(), resp.url(), resp.headers()) {
+                Some(next) => next.to_string(),
+                None => return read_sitemap_blocking(resp),
+            };
+            drop(permit);
+            if !self.follows_sitemap_redirect(&chain, &next) {
+                return None;
+            }
+            self.load_robots_for_blocking(client, &next, hosts);
+            if !self.sitemap_allowed(&next) {
+                return None;
+            }
+            chain.push(next);
+        }
+    }
+
+    /// Reads the site's sitemaps before the crawl starts. Each sitemap waits its turn for its host
+    /// like any other request and sitemaps the robots.txt of their origin disallows are passed
+    /// over. Sitemaps listed in an index and redirects are only followed inside the crawl's
+    /// scope, the same as a page's redirects.
+    pub(crate) async fn load_sitemaps(
+        self,
+        client: &Client,
+        hosts: &Arc<HostLimiter>,
+    ) -> Result<Self, CrawlError> {
+        let mut new = self;
+        if !new.config.sitemaps || new.sitemaps.is_some() {
+            return Ok(new);
+        }
+
+        new.sitemaps = Some(vec![]);
+        let mut queue = new.sitemap_queue();
+        while let Some(url) = queue.pop() {
+            new.load_robots_for(client, &url, hosts).await;
+            if !new.sitemap_allowed(&url) {
+                continue;
+            }
+            // Missing sitemaps are common so they're passed over.
+            let bytes = new.get_sitemap(client, &url, hosts).await;
+            if let Some(xml) = bytes.as_deref().and_then(decompress) {
+                let sitemap = Sitemap::parse(&url, &xml);
+                new.add_sitemap(url, sitemap, &mut queue)?;
+            }
+        }
+
+        Ok(new)
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn load_sitemaps_blocking(
+        self,
+        client: &reqwest::blocking::Client,
+        hosts: &Arc<HostLimiter>,
+    ) -> Result<Self, CrawlError> {
+        let mut new = self;
+        if !new.config.sitemaps || new.sitemaps.is_some() {
+            return Ok(new);
+        }
+
+        new.sitemaps = Some(vec![]);
+        let mut queue = new.sitemap_queue();
+        while let Some(url) = queue.pop() {
+            new.load_robots_for_blocking(client, &url, hosts);
+            if !new.sitemap_allowed(&url) {
+                continue;
+            }
+            let bytes = new.get_sitemap_blocking(client, &url, hosts);
+            if let Some(xml) = bytes.as_deref().and_then(decompress) {
+                let sitemap = Sitemap::parse(&url, &xml);
+                new.add_sitemap(url, sitemap, &mut queue)?;
+            }
+        }
+
+        Ok(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, ChangeFreq, Sitemap};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn sitemap_parse_test() {
+        let url = "https://test.com/sitemap.xml";
+        let sitemap = Sitemap::parse(
+            url,
+            b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\"\
+                xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\">\
+                <url>\
+                    <loc> https://test.com/a?x=1&amp;y=2 </loc>\
+                    <lastmod>2021-03-01</lastmod>\
+                    <changefreq>Weekly</changefreq>\
+                    <priority>0.8</priority>\
+                    <image:image><image:loc>https://test.com/a.png</image:loc></image:image>\
+                </url>\
+                <url><loc><![CDATA[https://test.com/b]]></loc><priority>2</priority></url>\
+                <url><lastmod>2021-03-01</lastmod></url>\
+            </urlset>",
+        );
+        assert!(sitemap.sitemaps.is_empty());
+        assert_eq!(sitemap.urls.len(), 2);
+        let (loc, entry) = &sitemap.urls[0];
+        assert_eq!(loc, "https://test.com/a?x=1&y=2");
+        assert_eq!(entry.sitemap(), url);
+        assert_eq!(entry.lastmod(), Some("2021-03-01"));
+        assert_eq!(entry.changefreq(), Some(ChangeFreq::Weekly));
+        assert_eq!(entry.priority(), Some(0.8));
+        let (loc, entry) = &sitemap.urls[1];
+        assert_eq!(loc, "https://test.com/b");
+        assert_eq!(entry.priority(), None);
+
+        let index = Sitemap::parse(
+            url,
+            b"<sitemapindex>\
+                <sitemap><loc>https://test.com/1.xml.gz</loc><lastmod>2021</lastmod></sitemap>\
+                <sitemap><loc>https://test.com/2.xml</loc></sitemap>\
+            </sitemapindex>",
+        );
+        assert_eq!(
+            index.sitemaps,
+            ["https://test.com/1.xml.gz", "https://test.com/2.xml"]
+        );
+        assert!(index.urls.is_empty());
+
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(b"<urlset/>").unwrap();
+        assert_eq!(decompress(&gz.finish().unwrap()).unwrap(), b"<urlset/>");
+        assert_eq!(decompress(b"<urlset/>").unwrap(), b"<urlset/>");
+    }
+}
@@ -0,0 +1,421 @@
+use super::checkpoint::{read_page, write_page, INSERT_PAGE, PAGES_SCHEMA};
+use super::{CrawlError, Page, PageState};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// How many pages are read from an SqliteStore at a time while iterating.
+const ITER_BATCH: usize = 1000;
+
+const FRONTIER_SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+CREATE TABLE IF NOT EXISTS frontier (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL
+);
+";
+
+/// Where a crawl keeps its pages and the queue of urls waiting to be requested. Every crawl mode
+/// works with any store. `MemoryStore` is used unless another store is given with
+/// `SimpleCrawler::with_store` or `CrawlerBuilder::build_with_store`.
+///
+/// A store queues a url whenever its page becomes pending, either because it's added pending or
+/// because a change left it pending when it wasn't before. Urls are taken from the queue in the
+/// order they were queued.
+pub trait CrawlStore: Send {
+    /// The page stored for a url.
+    fn get(&self, url: &str) -> Result<Option<Page>, CrawlError>;
+
+    /// Stores a page for a url which isn't known yet. Returns false and leaves the store as it
+    /// was if the url is already known.
+    fn insert_if_absent(&mut self, url: &str, page: Page) -> Result<bool, CrawlError>;
+
+    /// Changes the page stored for a url. Returns false if the url isn't known.
+    fn modify(&mut self, url: &str, f: &mut dyn FnMut(&mut Page)) -> Result<bool, CrawlError>;
+
+    /// Takes the next url from the queue. Urls whose page stopped being pending while they were
+    /// queued are passed over. The page stays pending until its state is changed.
+    fn next_pending(&mut self) -> Result<Option<String>, CrawlError>;
+
+    /// Every url and page in the store in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<(String, Page), CrawlError>> + '_>;
+
+    /// Stores a page for a url replacing any page already stored for it.
+    fn insert(&mut self, url: &str, page: Page) -> Result<(), CrawlError> {
+        if !self.insert_if_absent(url, page.to_owned())? {
+            self.modify(url, &mut |known| *known = page.to_owned())?;
+        }
+        Ok(())
+    }
+
+    /// Sets the state of the page stored for a url. Returns false if the url isn't known.
+    fn mark_state(&mut self, url: &str, state: PageState) -> Result<bool, CrawlError> {
+        self.modify(url, &mut |page| page.state = state.to_owned())
+    }
+}
+
+/// Keeps every page in a `HashMap`. It derefs to the map so a finished crawl can be read with for
+/// example `simple_crawler.urls[url]`. Two stores are equal if they hold the same pages.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    pages: HashMap<String, Page>,
+    queue: VecDeque<String>,
+}
+
+impl MemoryStore {
+    /// Create an empty MemoryStore.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl PartialEq for MemoryStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.pages == other.pages
+    }
+}
+
+// Only the pages are serialized. The queue is rebuilt from the pending pages in url order.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MemoryStore {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.pages.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MemoryStore {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pages = HashMap::<String, Page>::deserialize(deserializer)?;
+        let mut queue = pages
+            .iter()
+            .filter(|(_, page)| page.is_pending())
+            .map(|(url, _)| url.to_owned())
+            .collect::<Vec<_>>();
+        queue.sort();
+        Ok(MemoryStore {
+            pages,
+            queue: queue.into(),
+        })
+    }
+}
+
+impl Deref for MemoryStore {
+    type Target = HashMap<String, Page>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pages
+    }
+}
+
+impl CrawlStore for MemoryStore {
+    fn get(&self, url: &str) -> Result<Option<Page>, CrawlError> {
+        Ok(self.pages.get(url).cloned())
+    }
+
+    fn insert_if_absent(&mut self, url: &str, page: Page) -> Result<bool, CrawlError> {
+        if self.pages.contains_key(url) {
+            return Ok(false);
+        }
+        if page.is_pending() {
+            self.queue.push_back(url.to_owned());
+        }
+        self.pages.insert(url.to_owned(), page);
+        Ok(true)
+    }
+
+    fn modify(&mut self, url: &str, f: &mut dyn FnMut(&mut Page)) -> Result<bool, CrawlError> {
+        let page = match self.pages.get_mut(url) {
+            Some(page) => page,
+            None => return Ok(false),
+        };
+        let was_pending = page.is_pending();
+        f(page);
+        if !was_pending && page.is_pending() {
+            self.queue.push_back(url.to_owned());
+        }
+        Ok(true)
+    }
+
+    fn next_pending(&mut self) -> Result<Option<String>, CrawlError> {
+        while let Some(url) = self.queue.pop_front() {
+            if self.pages.get(&url).is_some_and(|page| page.is_pending()) {
+                return Ok(Some(url));
+            }
+        }
+        Ok(None)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<(String, Page), CrawlError>> + '_> {
+        Box::new(
+            self.pages
+                .iter()
+                .map(|(url, page)| Ok((url.to_owned(), page.to_owned()))),
+        )
+    }
+}
+
+/// Keeps pages and the queue in an SQLite database on disk so a crawl can grow far larger than
+/// memory. Pages are stored the same way as a checkpoint. Bodies are never stored in any store as
+/// the crawl is done with them once links have been found. The database uses a write-ahead log so
+/// a crash can't corrupt it and at most the last few writes are lost if the machine loses power.
+///
+/// Some of the crawl is still held in memory whatever the store: the aliases of pages which named
+/// a canonical url or were redirected and a count of query urls for each path. Both grow with the
+/// crawl but only by one entry for each such page or path.
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Opens the store at a path, creating it if it doesn't exist. Pages already in the store are
+    /// kept and any which are still pending are queued again so a crawl which was interrupted
+    /// can be carried on with the same store.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, CrawlError> {
+        let path = path.as_ref();
+        let store = SqliteStore {
+            path: path.to_owned(),
+            conn: Connection::open(path).map_err(store_error)?,
+        };
+        store
+            .conn
+            .execute_batch(FRONTIER_SCHEMA)
+            .and_then(|_| store.conn.execute_batch(PAGES_SCHEMA))
+            .and_then(|_| {
+                store.conn.execute(
+                    "INSERT INTO frontier (url) SELECT url FROM pages
+                    WHERE state = 'pending' AND url NOT IN (SELECT url FROM frontier)
+                    ORDER BY rowid",
+                    [],
+                )
+            })
+            .map_err(store_error)?;
+        Ok(store)
+    }
+
+    /// Where the store is on disk.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn put(&self, url: &str, page: &Page) -> rusqlite::Result<()> {
+        let mut insert = self.conn.prepare_cached(INSERT_PAGE)?;
+        write_page(&mut insert, url, page)
+    }
+
+    fn queue(&self, url: &str) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached("INSERT INTO frontier (url) VALUES (?1)")?
+            .execute(params![url])?;
+        Ok(())
+    }
+
+    // The pages after a url in url order.
+    fn batch(&self, after: &str) -> rusqlite::Result<Vec<(String, Page)>> {
+        let mut select = self
+            .conn
+            .prepare_cached("SELECT * FROM pages WHERE url > ?1 ORDER BY url LIMIT ?2")?;
+        let pages = select.query_map(params![after, ITER_BATCH as i64], read_page)?;
+        pages.collect()
+    }
+}
+
+impl fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteStore")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl CrawlStore for SqliteStore {
+    fn get(&self, url: &str) -> Result<Option<Page>, CrawlError> {
+        self.conn
+            .prepare_cached("SELECT * FROM pages WHERE url = ?1")
+            .and_then(|mut select| select.query_row(params![url], read_page).optional())
+            .map(|found| found.map(|(_, page)| page))
+            .map_err(store_error)
+    }
+
+    fn insert_if_absent(&mut self, url: &str, page: Page) -> Result<bool, CrawlError> {
+        if self.get(url)?.is_some() {
+            return Ok(false);
+        }
+        self.put(url, &page)
+            .and_then(|_| match page.is_pending() {
+                true => self.queue(url),
+                false => Ok(()),
+            })
+            .map_err(store_error)?;
+        Ok(true)
+    }
+
+    fn modify(&mut self, url: &str, f: &mut dyn FnMut(&mut Page)) -> Result<bool, CrawlError> {
+        let mut page = match self.get(url)? {
+            Some(page) => page,
+            None => return Ok(false),
+        };
+        let was_pending = page.is_pending();
+        f(&mut page);
+        self.put(url, &page)
+            .and_then(|_| match !was_pending && page.is_pending() {
+                true => self.queue(url),
+                false => Ok(()),
+            })
+            .map_err(store_error)?;
+        Ok(true)
+    }
+
+    fn next_pending(&mut self) -> Result<Option<String>, CrawlError> {
+        loop {
+            let next = self
+                .conn
+                .query_row(
+                    "SELECT seq, url FROM frontier ORDER BY seq LIMIT 1",
+                    [],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()
+                .map_err(store_error)?;
+            let (seq, url) = match next {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+            self.conn
+                .execute("DELETE FROM frontier WHERE seq = ?1", params![seq])
+                .map_err(store_error)?;
+            if self.get(&url)?.is_some_and(|page| page.is_pending()) {
+                return Ok(Some(url));
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<(String, Page), CrawlError>> + '_> {
+        Box::new(SqlitePages {
+            store: self,
+            after: "".to_owned(),
+            batch: VecDeque::new(),
+            done: false,
+        })
+    }
+}
+
+// Reads the pages of an SqliteStore a batch at a time so they're never all held in memory.
+struct SqlitePages<'a> {
+    store: &'a SqliteStore,
+    after: String,
+    batch: VecDeque<(String, Page)>,
+    done: bool,
+}
+
+impl Iterator for SqlitePages<'_> {
+    type Item = Result<(String, Page), CrawlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty() && !self.done {
+            match self.store.batch(&self.after) {
+                Ok(batch) => {
+                    self.done = batch.len() < ITER_BATCH;
+                    self.batch = batch.into();
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(store_error(e)));
+                }
+            }
+        }
+        let (url, page) = self.batch.pop_front()?;
+        self.after = url.to_owned();
+        Some(Ok((url, page)))
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> CrawlError {
+    CrawlError::Store {
+        source: Arc::new(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CrawlStore, MemoryStore, SqliteStore};
+    use crate::{CrawlError, Page, PageState, StoredBody};
+
+    // Every store should queue and hand out urls the same way.
+    fn check_store<S: CrawlStore>(store: &mut S) -> Result<(), CrawlError> {
+        assert!(store.insert_if_absent("https://test.com/a", Page::new())?);
+        assert!(store.insert_if_absent("https://test.com/b", Page::new())?);
+        assert!(!store.insert_if_absent("https://test.com/a", Page::new())?);
+        assert!(store.insert_if_absent(
+            "https://test.com/c",
+            Page {
+                state: PageState::Fetched,
+                ..Page::new()
+            },
+        )?);
+
+        assert_eq!(store.next_pending()?, Some("https://test.com/a".to_owned()));
+        // A url which stops being pending while queued is passed over and one which becomes
+        // pending joins the back of the queue.
+        assert!(store.mark_state("https://test.com/b", PageState::Fetched)?);
+        assert!(store.mark_state("https://test.com/c", PageState::Pending)?);
+        assert!(store.modify("https://test.com/c", &mut |page| page.depth = 2)?);
+        assert!(!store.mark_state("https://test.com/d", PageState::Fetched)?);
+        assert_eq!(store.next_pending()?, Some("https://test.com/c".to_owned()));
+        assert_eq!(store.next_pending()?, None);
+        assert_eq!(
+            store.get("https://test.com/c")?.map(|page| page.depth()),
+            Some(2)
+        );
+        let body = StoredBody::Memory("<p>b</p>".to_owned());
+        store.modify("https://test.com/b", &mut |page| {
+            page.stored_body = Some(body.to_owned())
+        })?;
+        assert_eq!(
+            store
+                .get("https://test.com/b")?
+                .and_then(|page| page.stored_body),
+            Some(body)
+        );
+
+        let mut urls = store
+            .iter()
+            .map(|result| result.map(|(url, _)| url))
+            .collect::<Result<Vec<_>, _>>()?;
+        urls.sort();
+        assert_eq!(
+            urls,
+            [
+                "https://test.com/a",
+                "https://test.com/b",
+                "https://test.com/c"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn memory_store_test() -> Result<(), CrawlError> {
+        check_store(&mut MemoryStore::new())
+    }
+
+    #[test]
+    fn sqlite_store_test() -> Result<(), CrawlError> {
+        let dir = std::env::temp_dir().join(format!("sqlite_store_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.db");
+
+        check_store(&mut SqliteStore::open(&path)?)?;
+        // Pages which were still pending are queued again when the store is reopened.
+        let mut store = SqliteStore::open(&path)?;
+        assert_eq!(store.next_pending()?, Some("https://test.com/a".to_owned()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use super::checkpoint::Checkpoint;
+use super::frontier::Workers;
+use super::limits::Budget;
+use super::politeness::HostLimiter;
+use super::store::CrawlStore;
+use super::{fetch, CrawlError, Limit, Link, Page, PageState, SimpleCrawler, SkipReason};
+use futures::{stream, Stream};
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+
+/// The events sent by `SimpleCrawler::crawl_stream` as the crawl happens.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CrawlEvent {
+    /// A page was fetched. The page still holds its body.
+    PageFetched { url: String, page: Page },
+    /// Every attempt to fetch a page failed.
+    PageFailed { url: String, page: Page },
+    /// A url which hadn't been seen before was found on a fetched page and queued to be
+    /// requested. It can still be skipped by robots.txt or a limit before it's requested.
+    LinkDiscovered { url: String, link: Link, depth: u32 },
+    /// A url which hadn't been seen before was found on a fetched page but won't be requested,
+    /// for example because it's past `max_depth` or only linked with `nofollow`.
+    LinkSkipped {
+        url: String,
+        link: Link,
+        depth: u32,
+        reason: SkipReason,
+    },
+    /// The crawl has finished. This is always the last event unless the crawl failed.
+    CrawlFinished {
+        fetched: usize,
+        failed: usize,
+        skipped: usize,
+        limit_reached: Option<Limit>,
+    },
+    /// The crawl stopped because its `CrawlStore` failed. This is always the last event.
+    CrawlFailed { error: CrawlError },
+}
+
+impl CrawlEvent {
+    /// The event for a page which has just been requested.
+    pub(crate) fn from_page(url: &str, page: &Page) -> Option<Self> {
+        match page.state {
+            PageState::Fetched => Some(CrawlEvent::PageFetched {
+                url: url.to_owned(),
+                page: page.to_owned(),
+            }),
+            PageState::Failed(_) => Some(CrawlEvent::PageFailed {
+                url: url.to_owned(),
+                page: page.to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The event for a url which has just been found.
+    pub(crate) fn from_link(url: &str, page: &Page) -> Option<Self> {
+        let link = page.discovered_by.to_owned()?;
+        let (url, depth) = (url.to_owned(), page.depth);
+        Some(match page.skip_reason() {
+            Some(reason) => CrawlEvent::LinkSkipped {
+                url,
+                link,
+                depth,
+                reason: reason.to_owned(),
+            },
+            None => CrawlEvent::LinkDiscovered { url, link, depth },
+        })
+    }
+}
+
+impl<S: CrawlStore + 'static> SimpleCrawler<S> {
+    /// Crawl concurrently sending each page as soon as it's fetched rather than returning once the
+    /// whole site is done. It can be used with
+    /// `let events = SimpleCrawler::new().url(&str)?.crawl_stream(usize)?` and must be called from
+    /// within a tokio runtime. The usize specifies how many concurrent requests are required.
+    /// Bodies are only held until the event for the page has been taken from the stream so a slow
+    /// consumer slows the crawl down rather than using more memory. Pages themselves are kept in
+    /// the crawler's store until the crawl finishes so memory is only bounded when they're kept
+    /// on disk, for example with `CrawlerBuilder::build_with_store(SqliteStore::open(path)?)`.
+    /// Dropping the stream stops the crawl.
+    pub fn crawl_stream(
+        self,
+        concurrent_requests: usize,
+    ) -> Result<impl Stream<Item = CrawlEvent>, CrawlError> {
+        let client = fetch::client(&self.config)?;
+        let checkpoint = Checkpoint::from_config(&self.config)?;
+        let (tx, rx) = tokio::sync::mpsc::channel(concurrent_requests.max(1));
+
+        tokio::spawn(self.stream_events(client, concurrent_requests, checkpoint, tx));
+
+        Ok(stream::unfold(rx, |mut rx| async {
+            rx.recv().await.map(|event| (event, rx))
+        }))
+    }
+
+    async fn stream_events(
+        self,
+        client: reqwest::Client,
+        concurrent_requests: usize,
+        checkpoint: Option<Checkpoint>,
+        tx: Sender<CrawlEvent>,
+    ) {
+        let budget = Budget::new(&self.config);
+        let event = match self
+            .crawl_events(&client, &budget, concurrent_requests, checkpoint, &tx)
+            .await
+        {
+            Ok(event) => event,
+            Err(error) => CrawlEvent::CrawlFailed { error },
+        };
+        let _ = tx.send(event).await;
+    }
+
+    // Runs the crawl and returns the event it finished with.
+    async fn crawl_events(
+        self,
+        client: &reqwest::Client,
+        budget: &Arc<Budget>,
+        concurrent_requests: usize,
+        checkpoint: Option<Checkpoint>,
+        tx: &Sender<CrawlEvent>,
+    ) -> Result<CrawlEvent, CrawlError> {
+        let hosts = HostLimiter::new(&self);
+        let new = self
+            .load_robots(client, &hosts)
+            .await
+            .load_sitemaps(client, &hosts)
+            .await?
+            .crawl_frontier(
+                client,
+                budget,
+                &hosts,
+                Workers::Concurrent(concurrent_requests),
+                checkpoint,
+                Some(tx),
+            )
+            .await?;
+
+        let (mut fetched, mut failed, mut skipped) = (0, 0, 0);
+        for page in new.urls.iter() {
+            match page?.1.state {
+                PageState::Fetched => fetched += 1,
+                PageState::Failed(_) => failed += 1,
+                PageState::Skipped(_) => skipped += 1,
+                PageState::Pending => {}
+            }
+        }
+        Ok(CrawlEvent::CrawlFinished {
+            fetched,
+            failed,
+            skipped,
+            limit_reached: new.limit_reached,
+        })
+    }
+}
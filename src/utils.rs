@@ -1,43 +1,105 @@
-use anyhow::{Context, Result};
+use super::{CrawlError, CrawlerConfig};
 use reqwest::Url;
 
-pub fn check_base_url(url: &str) -> Result<String> {
-    let new_url = Url::parse(url).with_context(|| format!("Base URL invalid"))?;
-    Ok(new_url.into_string())
+pub fn check_base_url(url: &str) -> Result<String, CrawlError> {
+    let new_url = Url::parse(url).map_err(|source| CrawlError::InvalidBaseUrl {
+        url: url.to_owned(),
+        source,
+    })?;
+    Ok(new_url.into())
 }
 
-pub fn normalise_url(base_url: &str, url: &str) -> Option<String> {
-    let new_base_url = Url::parse(base_url);
-    let new_url = Url::parse(url);
-
-    if new_base_url.is_ok() {
-        let nbu = new_base_url.unwrap();
-        match new_url {
-            Ok(new_url) => {
-                // Only allow URLs from domain.
-                // Ignore URLS with ? for get requests and # for client side frameworks.
-                if nbu.to_owned().host_str() == new_url.host_str()
-                    && !url.contains("?")
-                    && !url.contains("#")
-                {
-                    Some(url.to_owned())
-                } else {
-                    None
-                }
-            }
-            Err(_e) => {
-                // Relative urls are not parsed by Reqwest.
-                // Ignore URLS with ? for get requests and # for client side frameworks.
-                if url.starts_with('/') && !url.contains("?") && !url.contains("#") {
-                    let scheme = nbu.scheme();
-                    let host = nbu.host_str().unwrap();
-                    Some(format!("{}://{}{}", scheme, host, url))
-                } else {
-                    None
-                }
-            }
-        }
+/// Resolves a link against the url of the page it was found on following RFC 3986, so relative,
+/// dot segment and protocol relative links all work, then canonicalizes it. Returns `None` if
+/// the url is outside the crawl's scope of `base_url`.
+pub fn normalise_url(
+    base_url: &str,
+    page_url: &str,
+    url: &str,
+    config: &CrawlerConfig,
+) -> Option<String> {
+    let nbu = Url::parse(base_url).ok()?;
+    let new_url = Url::parse(page_url).ok()?.join(url.trim()).ok()?;
+    let new_url = config.canonicalizer.canonicalize(&new_url);
+
+    // Only allow URLs in scope.
+    if config.scope.in_scope(&nbu, &new_url) {
+        Some(new_url.into())
     } else {
         None
     }
 }
+
+/// The url links on a page are resolved against. This is the `<base href>` if the page has one,
+/// itself resolved against the page's url, otherwise the page's url.
+pub fn page_base_url(page_url: &str, base_href: Option<&str>) -> String {
+    base_href
+        .and_then(|href| Url::parse(page_url).ok()?.join(href.trim()).ok())
+        .map(|url| url.into())
+        .unwrap_or_else(|| page_url.to_owned())
+}
+
+/// The scheme, host and port of a url, for example `https://test.com:8080`. Default ports are
+/// left out.
+pub fn origin(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    url.has_host().then(|| url.origin().ascii_serialization())
+}
+
+pub fn robots_url(base_url: &str) -> Option<String> {
+    let url = Url::parse(base_url).ok()?.join("/robots.txt").ok()?;
+    Some(url.into())
+}
+
+pub fn sitemap_url(base_url: &str) -> Option<String> {
+    let url = Url::parse(base_url).ok()?.join("/sitemap.xml").ok()?;
+    Some(url.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalise_url, page_base_url};
+    use crate::CrawlerConfig;
+
+    #[test]
+    fn normalise_url_test() {
+        let base = "https://test.com/";
+        let page = "https://test.com/docs/guide/index.html";
+        let config = CrawlerConfig::default();
+        let resolve = |url| normalise_url(base, page, url, &config);
+
+        assert_eq!(
+            resolve("page.html"),
+            Some("https://test.com/docs/guide/page.html".to_owned())
+        );
+        assert_eq!(
+            resolve("../about"),
+            Some("https://test.com/docs/about".to_owned())
+        );
+        assert_eq!(
+            resolve("./x"),
+            Some("https://test.com/docs/guide/x".to_owned())
+        );
+        assert_eq!(
+            resolve("/a/./b/../c"),
+            Some("https://test.com/a/c".to_owned())
+        );
+        assert_eq!(
+            resolve("//test.com/path"),
+            Some("https://test.com/path".to_owned())
+        );
+        assert_eq!(resolve("//other.com/path"), None);
+        assert_eq!(
+            resolve("page?q=1&utm_source=feed#top"),
+            Some("https://test.com/docs/guide/page?q=1".to_owned())
+        );
+        assert_eq!(resolve("mailto:someone@test.com"), None);
+
+        let page = page_base_url(page, Some("/base/"));
+        assert_eq!(page, "https://test.com/base/");
+        assert_eq!(
+            normalise_url(base, &page, "x", &config),
+            Some("https://test.com/base/x".to_owned())
+        );
+    }
+}
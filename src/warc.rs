@@ -0,0 +1,543 @@
+use super::{CrawlError, CrawlerConfig, Page, DEFAULT_USER_AGENT};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{HeaderMap, TRANSFER_ENCODING};
+use reqwest::{StatusCode, Url, Version};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BASE32: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// The Archive struct sets where a crawl is written as WARC 1.1 files so it can be replayed. Every
+/// request made for a page, retries and redirects included, is written as a request and a
+/// response record followed by a metadata record saying how the page was found. Each file starts
+/// with a warcinfo record. robots.txt and sitemaps aren't archived. For example
+/// `let simple_crawler = SimpleCrawler::builder().archive(Archive::new("warcs").prefix("site")).build()`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Archive {
+    dir: PathBuf,
+    prefix: String,
+    gzip: bool,
+    max_file_size: u64,
+}
+
+impl Archive {
+    /// Writes gzipped files of up to a gigabyte to a directory, creating it if it doesn't exist.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Archive {
+            dir: dir.as_ref().to_owned(),
+            prefix: "crawl".to_owned(),
+            gzip: true,
+            max_file_size: 1_000_000_000,
+        }
+    }
+
+    /// What the name of every file starts with. It's followed by when the crawl started and the
+    /// number of the file, for example `crawl-20240101120000-00000.warc.gz`.
+    pub fn prefix(self, prefix: &str) -> Self {
+        let mut new = self;
+        new.prefix = prefix.to_owned();
+        new
+    }
+
+    /// Whether each record is gzipped on its own so readers can seek to any record. This is on by
+    /// default.
+    pub fn gzip(self, gzip: bool) -> Self {
+        let mut new = self;
+        new.gzip = gzip;
+        new
+    }
+
+    /// Starts a new file once the current one is at least this many bytes. A page's records are
+    /// never split between files so files can be a little larger than this.
+    pub fn max_file_size(self, max_file_size: u64) -> Self {
+        let mut new = self;
+        new.max_file_size = max_file_size;
+        new
+    }
+
+    /// The directory files are written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// A request and the response it got exactly as they're archived. Bodies are kept as the bytes
+/// which were read, so they're still compressed if the server compressed them, but they've been
+/// taken out of any chunked transfer encoding so that header is left out.
+#[derive(Clone, Debug)]
+pub(crate) struct Exchange {
+    url: String,
+    date: SystemTime,
+    ip: Option<SocketAddr>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+    payload: usize,
+}
+
+impl Exchange {
+    /// The GET request sent for a url. The client only adds these headers to each request.
+    pub(crate) fn new(url: &str, user_agent: &str, date: SystemTime) -> Self {
+        let request = match Url::parse(url) {
+            Ok(parsed) => {
+                let host = match parsed.port() {
+                    Some(port) => format!("{}:{}", parsed.host_str().unwrap_or_default(), port),
+                    None => parsed.host_str().unwrap_or_default().to_owned(),
+                };
+                let target = &parsed[url::Position::BeforePath..url::Position::AfterQuery];
+                format!(
+                    "GET {} HTTP/1.1\r\nhost: {}\r\nuser-agent: {}\r\naccept: */*\r\n\r\n",
+                    target, host, user_agent
+                )
+            }
+            Err(_) => format!("GET {} HTTP/1.1\r\n\r\n", url),
+        };
+        Exchange {
+            url: url.to_owned(),
+            date,
+            ip: None,
+            request: request.into_bytes(),
+            response: vec![],
+            payload: 0,
+        }
+    }
+
+    /// The status line and headers of the response.
+    pub(crate) fn respond(
+        self,
+        version: Version,
+        status: StatusCode,
+        headers: &HeaderMap,
+        ip: Option<SocketAddr>,
+    ) -> Self {
+        let mut new = self;
+        let reason = status.canonical_reason().unwrap_or_default();
+        new.response = format!("{:?} {} {}\r\n", version, status.as_u16(), reason).into_bytes();
+        for (name, value) in headers.iter().filter(|(n, _)| *n != TRANSFER_ENCODING) {
+            new.response.extend_from_slice(name.as_str().as_bytes());
+            new.response.extend_from_slice(b": ");
+            new.response.extend_from_slice(value.as_bytes());
+            new.response.extend_from_slice(b"\r\n");
+        }
+        new.response.extend_from_slice(b"\r\n");
+        new.payload = new.response.len();
+        new.ip = ip;
+        new
+    }
+
+    /// The body of the response.
+    pub(crate) fn body(self, body: &[u8]) -> Self {
+        let mut new = self;
+        new.response.extend_from_slice(body);
+        new
+    }
+}
+
+/// Writes the WARC files of a single crawl. Nothing is written until the first record so a crawl
+/// which fetches nothing leaves no files behind.
+pub(crate) struct WarcWriter {
+    archive: Archive,
+    started: String,
+    serial: u32,
+    file: Option<(PathBuf, File, u64)>,
+}
+
+impl WarcWriter {
+    /// A writer for the archive set with `CrawlerBuilder::archive` if there is one.
+    pub(crate) fn from_config(config: &CrawlerConfig) -> Option<Self> {
+        let archive = config.archive.to_owned()?;
+        Some(WarcWriter {
+            archive,
+            started: timestamp(SystemTime::now()).replace(['-', ':', 'T', 'Z'], ""),
+            serial: 0,
+            file: None,
+        })
+    }
+
+    /// Writes each request made for a page and its response followed by a metadata record for
+    /// the page. Pages which weren't requested write nothing.
+    pub(crate) fn write(
+        &mut self,
+        url: &str,
+        page: &Page,
+        exchanges: &[Exchange],
+    ) -> Result<(), CrawlError> {
+        let full = match &self.file {
+            Some((_, _, size)) => *size >= self.archive.max_file_size,
+            None => false,
+        };
+        if full && !exchanges.is_empty() {
+            self.file = None;
+        }
+
+        let mut response_id = None;
+        for exchange in exchanges {
+            let id = record_id();
+            let mut headers = vec![("WARC-Concurrent-To", id.to_owned())];
+            if let Some(ip) = exchange.ip {
+                headers.push(("WARC-IP-Address", ip.ip().to_string()));
+            }
+            let request = Record {
+                kind: "request",
+                id: record_id(),
+                url: Some(&exchange.url),
+                date: exchange.date,
+                headers,
+                content_type: "application/http;msgtype=request",
+                block: &exchange.request,
+            };
+            self.write_record(&request)?;
+
+            let mut headers = vec![(
+                "WARC-Payload-Digest",
+                digest(&exchange.response[exchange.payload..]),
+            )];
+            if let Some(ip) = exchange.ip {
+                headers.push(("WARC-IP-Address", ip.ip().to_string()));
+            }
+            let response = Record {
+                kind: "response",
+                id,
+                url: Some(&exchange.url),
+                date: exchange.date,
+                headers,
+                content_type: "application/http;msgtype=response",
+                block: &exchange.response,
+            };
+            self.write_record(&response)?;
+            response_id = Some(response.id);
+        }
+
+        let response_id = match response_id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let mut fields = vec![];
+        if let Some(link) = page.discovered_by() {
+            fields.push(format!("via: {}\r\n", link.source()));
+        }
+        fields.push(format!("depth: {}\r\n", page.depth()));
+        fields.push(format!("attempts: {}\r\n", page.attempts()));
+        if let Some(latency) = page.latency() {
+            fields.push(format!("fetchTimeMs: {}\r\n", latency.as_millis()));
+        }
+        let fields = fields.concat();
+        let metadata = Record {
+            kind: "metadata",
+            id: record_id(),
+            url: Some(url),
+            date: exchanges
+                .last()
+                .map(|e| e.date)
+                .unwrap_or_else(SystemTime::now),
+            headers: vec![("WARC-Concurrent-To", response_id)],
+            content_type: "application/warc-fields",
+            block: fields.as_bytes(),
+        };
+        self.write_record(&metadata)
+    }
+
+    // Opens a new file starting with a warcinfo record when there isn't one.
+    fn write_record(&mut self, record: &Record) -> Result<(), CrawlError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        let bytes = record.to_bytes(self.archive.gzip);
+        let (path, file, size) = self.file.as_mut().expect("a WARC file is open");
+        file.write_all(&bytes).map_err(|e| warc_error(path, e))?;
+        *size += bytes.len() as u64;
+        Ok(())
+    }
+
+    // Files are never overwritten. If another crawl writing to the same directory with the same
+    // prefix started in the same second the next number which isn't taken is used.
+    fn open(&mut self) -> Result<(), CrawlError> {
+        let extension = if self.archive.gzip { "warc.gz" } else { "warc" };
+        fs::create_dir_all(&self.archive.dir).map_err(|e| warc_error(&self.archive.dir, e))?;
+        let (name, path, file) = loop {
+            let name = format!(
+                "{}-{}-{:05}.{}",
+                self.archive.prefix, self.started, self.serial, extension
+            );
+            let path = self.archive.dir.join(&name);
+            self.serial += 1;
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break (name, path, file),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(warc_error(&path, e)),
+            }
+        };
+        self.file = Some((path, file, 0));
+
+        let info = format!(
+            "software: {}\r\nformat: WARC File Format 1.1\r\n\
+            conformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+            DEFAULT_USER_AGENT
+        );
+        let warcinfo = Record {
+            kind: "warcinfo",
+            id: record_id(),
+            url: None,
+            date: SystemTime::now(),
+            headers: vec![("WARC-Filename", name)],
+            content_type: "application/warc-fields",
+            block: info.as_bytes(),
+        };
+        self.write_record(&warcinfo)
+    }
+}
+
+struct Record<'a> {
+    kind: &'a str,
+    id: String,
+    url: Option<&'a str>,
+    date: SystemTime,
+    headers: Vec<(&'a str, String)>,
+    content_type: &'a str,
+    block: &'a [u8],
+}
+
+impl Record<'_> {
+    fn to_bytes(&self, gzip: bool) -> Vec<u8> {
+        let mut head = format!(
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+            self.kind,
+            self.id,
+            timestamp(self.date)
+        );
+        if let Some(url) = self.url {
+            head.push_str(&format!("WARC-Target-URI: {}\r\n", url));
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "WARC-Block-Digest: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            digest(self.block),
+            self.content_type,
+            self.block.len()
+        ));
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(self.block);
+        bytes.extend_from_slice(b"\r\n\r\n");
+        if !gzip {
+            return bytes;
+        }
+        // Writing to a Vec can't fail.
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let _ = encoder.write_all(&bytes);
+        encoder.finish().unwrap_or_default()
+    }
+}
+
+fn warc_error(path: &Path, e: std::io::Error) -> CrawlError {
+    CrawlError::Warc {
+        path: path.to_owned(),
+        source: Arc::new(e),
+    }
+}
+
+// A random version 4 UUID.
+fn record_id() -> String {
+    let mut bytes = rand::random::<[u8; 16]>();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "<urn:uuid:{}-{}-{}-{}-{}>",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// Digests are base32 like most WARC tools write them.
+fn digest(bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes);
+    let mut encoded = String::new();
+    for chunk in hash.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer.iter().fold(0u64, |bits, b| (bits << 8) | *b as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..8 {
+            match i < chars {
+                true => encoded.push(BASE32[((bits >> (35 - i * 5)) & 31) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    format!("sha256:{}", encoded)
+}
+
+// The UTC date and time to the second as `2024-01-01T12:00:00Z`.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Howard Hinnant's days to civil date algorithm.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{digest, timestamp, Archive, Exchange, WarcWriter};
+    use crate::{CrawlError, CrawlerConfig, Page, PageState};
+    use flate2::read::MultiGzDecoder;
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, TRANSFER_ENCODING};
+    use reqwest::{StatusCode, Version};
+    use std::io::Read;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn warc_test() -> Result<(), CrawlError> {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "2024-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            digest(b""),
+            "sha256:4OYMIQUY7QOBJGX36TEJS35ZEQT24QPEMSNZGTFESWMRW6CSXBKQ===="
+        );
+
+        let dir = std::env::temp_dir().join(format!("warc_test_{}", std::process::id()));
+        let config = CrawlerConfig {
+            archive: Some(Archive::new(&dir).prefix("test").max_file_size(1)),
+            ..CrawlerConfig::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        let exchange = Exchange::new("https://test.com:8080/a?b=c", "test", UNIX_EPOCH)
+            .respond(Version::HTTP_11, StatusCode::OK, &headers, None)
+            .body(b"<p>a</p>");
+        let page = Page {
+            state: PageState::Fetched,
+            depth: 2,
+            attempts: 1,
+            ..Page::new()
+        };
+
+        let mut writer = WarcWriter::from_config(&config).unwrap();
+        writer.write("https://test.com:8080/a?b=c", &page, &[exchange.to_owned()])?;
+        // Nothing is written for a page which wasn't requested.
+        writer.write("https://test.com/b", &Page::new(), &[])?;
+        writer.write("https://test.com:8080/a?b=c", &page, &[exchange])?;
+
+        // Every page went over the size so each one has a file of its own after a warcinfo.
+        let mut files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), 2);
+        let mut warc = String::new();
+        MultiGzDecoder::new(std::fs::File::open(&files[1]).unwrap())
+            .read_to_string(&mut warc)
+            .unwrap();
+        let records = warc.split("WARC/1.1\r\n").skip(1).collect::<Vec<_>>();
+        assert_eq!(records.len(), 4);
+        assert!(records[0].starts_with("WARC-Type: warcinfo\r\n"));
+        assert!(records[1].starts_with("WARC-Type: request\r\n"));
+        assert!(records[1].ends_with(concat!(
+            "GET /a?b=c HTTP/1.1\r\nhost: test.com:8080\r\nuser-agent: test\r\n",
+            "accept: */*\r\n\r\n\r\n\r\n"
+        )));
+        assert!(records[2].contains("WARC-Target-URI: https://test.com:8080/a?b=c\r\n"));
+        assert!(records[2].contains("Content-Type: application/http;msgtype=response\r\n"));
+        assert!(records[2].ends_with(concat!(
+            "Content-Length: 52\r\n\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\r\n<p>a</p>\r\n\r\n"
+        )));
+        assert!(records[3].starts_with("WARC-Type: metadata\r\n"));
+        assert!(records[3].ends_with("depth: 2\r\nattempts: 1\r\n\r\n\r\n"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn warc_names_test() -> Result<(), CrawlError> {
+        let dir = std::env::temp_dir().join(format!("warc_names_test_{}", std::process::id()));
+        let config = CrawlerConfig {
+            archive: Some(Archive::new(&dir).prefix("test").gzip(false)),
+            ..CrawlerConfig::default()
+        };
+        let page = Page {
+            state: PageState::Fetched,
+            ..Page::new()
+        };
+        let exchange = |url| {
+            Exchange::new(url, "test", UNIX_EPOCH)
+                .respond(Version::HTTP_11, StatusCode::OK, &HeaderMap::new(), None)
+                .body(b"<p>a</p>")
+        };
+
+        // Two crawls writing to the same directory at once don't overwrite each other's files.
+        let mut first = WarcWriter::from_config(&config).unwrap();
+        let mut second = WarcWriter::from_config(&config).unwrap();
+        first.write(
+            "https://test.com/a",
+            &page,
+            &[exchange("https://test.com/a")],
+        )?;
+        second.write(
+            "https://test.com/b",
+            &page,
+            &[exchange("https://test.com/b")],
+        )?;
+        first.write(
+            "https://test.com/c",
+            &page,
+            &[exchange("https://test.com/c")],
+        )?;
+
+        let mut files = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<Vec<_>>();
+        files.sort_by_key(|warc| warc.contains("https://test.com/b"));
+        assert_eq!(files.len(), 2);
+        assert!(files[0].contains("WARC-Target-URI: https://test.com/a\r\n"));
+        assert!(files[0].contains("WARC-Target-URI: https://test.com/c\r\n"));
+        assert_eq!(files[0].matches("WARC-Type: warcinfo\r\n").count(), 1);
+        assert!(files[1].starts_with("WARC/1.1\r\nWARC-Type: warcinfo\r\n"));
+        assert!(files[1].contains("WARC-Target-URI: https://test.com/b\r\n"));
+        assert!(!files[1].contains("https://test.com/a"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+}
@@ -1,5 +1,17 @@
 use anyhow::{Context, Result};
-use simple_crawler::SimpleCrawler;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::{FutureExt, StreamExt};
+use simple_crawler::select::document::Document;
+use simple_crawler::select::predicate::Name;
+use simple_crawler::{
+    Archive, BodyRetention, ChangeFreq, CrawlError, CrawlEvent, CrawlStore, ExportFormat, Exporter,
+    Handled, Limit, LinkKind, Page, Politeness, RedirectPolicy, RetryPolicy, ScopePolicy,
+    SimpleCrawler, SkipReason, SqliteStore, StoredBody,
+};
+use std::io::{Read, Write};
+use std::time::Duration;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
@@ -24,17 +36,17 @@ impl SimpleCrawlerMock {
     }
 }
 
-async fn setup_mocks() -> Result<String> {
+async fn setup_mocks() -> Result<SimpleCrawlerMock> {
     // start the mock server
     let mock = SimpleCrawlerMock::new()
         .await
-        .with_context(|| format!("Failed to start mock server"))?;
+        .context("Failed to start mock server")?;
 
     // url of mock server
-    let mock_url = &mock.mock_server.uri().to_owned();
+    let mock_url = &mock.mock_server.uri();
 
     // setup mocks
-    mock
+    let mock = mock
         // mock1
         .mock(
             "GET",
@@ -44,10 +56,10 @@ async fn setup_mocks() -> Result<String> {
                 <a href=\"{mock_url}/crawl3\">aaa</a>",
                 mock_url = mock_url
             )
-                .as_ref(),
+            .as_ref(),
         )
         .await
-        .with_context(|| format!("Failed to add mock1"))?
+        .context("Failed to add mock1")?
         // mock 2
         .mock(
             "GET",
@@ -57,10 +69,10 @@ async fn setup_mocks() -> Result<String> {
                 <a href=\"{mock_url}/crawl4\">aaa</a>",
                 mock_url = mock_url
             )
-                .as_ref(),
+            .as_ref(),
         )
         .await
-        .with_context(|| format!("Failed to add mock2"))?
+        .context("Failed to add mock2")?
         // mock 3
         .mock(
             "GET",
@@ -70,10 +82,10 @@ async fn setup_mocks() -> Result<String> {
                 <a href=\"{mock_url}/crawl6\">aaa</a>",
                 mock_url = mock_url
             )
-                .as_ref(),
+            .as_ref(),
         )
         .await
-        .with_context(|| format!("Failed to add mock3"))?
+        .context("Failed to add mock3")?
         // mock 4
         .mock(
             "GET",
@@ -83,11 +95,11 @@ async fn setup_mocks() -> Result<String> {
                 <a href=\"{mock_url}/crawl8\">aaa</a>",
                 mock_url = mock_url
             )
-                .as_ref(),
+            .as_ref(),
         )
         .await
-        .with_context(|| format!("Failed to add mock3"))?;
-    Ok(mock_url.to_owned())
+        .context("Failed to add mock4")?;
+    Ok(mock)
 }
 
 fn mock_expected_results(mock_url: String) -> Vec<String> {
@@ -107,63 +119,1858 @@ fn mock_expected_results(mock_url: String) -> Vec<String> {
 
 #[tokio::test]
 async fn crawl_test() -> Result<()> {
-
-    let mock_url = setup_mocks().await
-        .with_context(|| format!("Failed to setup mock server"))?;
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
 
     // do crawl
     let simple_crawler = SimpleCrawler::new()
         .url(format!("{}/crawl", mock_url).as_str())?
         .crawl()
+        .await?;
+    let expected = mock_expected_results(mock_url);
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_concurrent_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_concurrent(2)
+        .await?;
+    let expected = mock_expected_results(mock_url);
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_parallel_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(2)
+        .await?;
+    let expected = mock_expected_results(mock_url);
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_requests_once_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(4)
+        .await?;
+
+    // crawl3 is linked to from two pages but is only requested once.
+    let requests = mock
+        .mock_server
+        .received_requests()
+        .await
+        .context("Requests weren't recorded")?;
+    let mut paths: Vec<String> = requests.iter().map(|r| r.url.path().to_owned()).collect();
+    paths.sort();
+    let expected = vec![
+        "/crawl",
+        "/crawl2",
+        "/crawl3",
+        "/crawl4",
+        "/crawl5",
+        "/crawl6",
+        "/crawl7",
+        "/crawl8",
+        "/robots.txt",
+    ];
+    assert_eq!(expected, paths);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_stream_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let seed = format!("{}/crawl", mock_url);
+
+    // do crawl
+    let events: Vec<CrawlEvent> = SimpleCrawler::new()
+        .url(&seed)?
+        .crawl_stream(2)?
+        .collect()
         .await;
-    if simple_crawler.is_ok() {
-        let expected = mock_expected_results(mock_url);
-        let mut actual: Vec<String> = simple_crawler.unwrap().urls.keys().cloned().collect();
-        actual.sort();
-        assert_eq!(expected, actual);
+
+    let mut fetched = vec![];
+    let mut discovered = vec![];
+    for event in &events {
+        match event {
+            CrawlEvent::PageFetched { url, page } => {
+                if url == &seed {
+                    assert!(page.body().contains("/crawl2"));
+                }
+                fetched.push(url.to_owned());
+            }
+            CrawlEvent::LinkDiscovered { url, link, depth } => {
+                assert!(fetched.contains(&link.source().to_owned()));
+                assert!(*depth > 0);
+                discovered.push(url.to_owned());
+            }
+            _ => {}
+        }
     }
+    fetched.sort();
+    discovered.sort();
+    let expected = mock_expected_results(mock_url);
+    assert_eq!(expected, fetched);
+    assert_eq!(expected[1..].to_vec(), discovered);
+    assert_eq!(
+        events.last(),
+        Some(&CrawlEvent::CrawlFinished {
+            fetched: 8,
+            failed: 0,
+            skipped: 0,
+            limit_reached: None,
+        })
+    );
 
     Ok(())
 }
 
 #[tokio::test]
-async fn crawl_concurrent_test() -> Result<()> {
+async fn crawl_stream_dropped_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let seed = format!("{}/crawl", mock_url);
+    let dir =
+        std::env::temp_dir().join(format!("crawl_stream_dropped_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let checkpoint = dir.join("crawl.db");
+
+    // Nobody takes the first page's event so the crawl stops there.
+    drop(
+        SimpleCrawler::builder()
+            .checkpoint(&checkpoint)
+            .build()
+            .url(&seed)?
+            .crawl_stream(1)?,
+    );
+
+    // The page is still recorded and checkpointed before the crawl stops.
+    let mut fetched = false;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let simple_crawler = SimpleCrawler::builder().build().resume(&checkpoint)?;
+        if simple_crawler
+            .urls
+            .get(&seed)?
+            .is_some_and(|page| page.crawled())
+        {
+            fetched = true;
+            break;
+        }
+    }
+    assert!(fetched);
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_stream_skipped_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // Urls past the depth limit are sent as skipped rather than discovered.
+    let events: Vec<CrawlEvent> = SimpleCrawler::builder()
+        .max_depth(1)
+        .build()
+        .url(&format!("{}/crawl", mock_url))?
+        .crawl_stream(2)?
+        .collect()
+        .await;
+
+    let mut discovered = vec![];
+    let mut skipped = vec![];
+    for event in events {
+        match event {
+            CrawlEvent::LinkDiscovered { url, .. } => discovered.push(url),
+            CrawlEvent::LinkSkipped { url, reason, .. } => {
+                assert_eq!(reason, SkipReason::Limit(Limit::MaxDepth));
+                skipped.push(url);
+            }
+            _ => {}
+        }
+    }
+    discovered.sort();
+    skipped.sort();
+    let url = |path: &str| format!("{}{}", mock_url, path);
+    assert_eq!(discovered, [url("/crawl2"), url("/crawl3")]);
+    assert_eq!(skipped, [url("/crawl4"), url("/crawl5"), url("/crawl6")]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_page_handler_test() -> Result<()> {
+    let mock = setup_mocks()
+        .await
+        .context("Failed to setup mock server")?
+        .mock("GET", "/hidden", "")
+        .await
+        .context("Failed to add hidden mock")?;
+    let mock_url = mock.mock_server.uri();
+
+    // Counts each page's anchors and asks for a url no page links to.
+    let handler = |url: &str, _: &Page, document: &Document| {
+        let anchors = document.find(Name("a")).count();
+        let handled = Handled::new().data("anchors", &anchors.to_string());
+        let handled = match url.ends_with("/crawl") {
+            true => handled.url("/hidden"),
+            false => handled,
+        };
+        async move { handled }.boxed()
+    };
+    let simple_crawler = SimpleCrawler::builder()
+        .page_handler(handler)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(2)
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl2", mock_url)];
+    assert_eq!(page.data()["anchors"], "2");
+    let hidden = &simple_crawler.urls[&format!("{}/hidden", mock_url)];
+    assert!(hidden.crawled());
+    assert_eq!(hidden.data()["anchors"], "0");
+    assert_eq!(hidden.depth(), 1);
+    assert_eq!(
+        hidden.discovered_by().map(|link| link.kind()),
+        Some(LinkKind::Handler)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_export_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let seed = format!("{}/crawl", mock_url);
+
+    // export a finished crawl
+    let simple_crawler = SimpleCrawler::new().url(&seed)?.crawl().await?;
+    let links: usize = simple_crawler
+        .urls
+        .values()
+        .map(|page| page.referrers().len())
+        .sum();
+    let json = String::from_utf8(simple_crawler.export(ExportFormat::JsonLines, vec![])?)?;
+    assert_eq!(json.lines().count(), 8);
+    assert!(json
+        .lines()
+        .all(|line| line.starts_with("{\"version\":1,\"url\":")
+            && line.contains("\"state\":\"fetched\"")));
+    let csv = String::from_utf8(simple_crawler.export(ExportFormat::Csv, vec![])?)?;
+    assert_eq!(csv.lines().count(), 9);
+    let dot = String::from_utf8(simple_crawler.export(ExportFormat::Dot, vec![])?)?;
+    assert_eq!(dot.matches(" -> ").count(), links);
+    let graphml = String::from_utf8(simple_crawler.export(ExportFormat::GraphMl, vec![])?)?;
+    assert_eq!(graphml.matches("<node ").count(), 8);
+    assert_eq!(graphml.matches("<edge ").count(), links);
+
+    // export a crawl as it streams
+    let mut exporter = Exporter::new(ExportFormat::GraphMl, vec![]);
+    let mut events = Box::pin(SimpleCrawler::new().url(&seed)?.crawl_stream(2)?);
+    while let Some(event) = events.next().await {
+        exporter.event(&event)?;
+    }
+    let graphml = String::from_utf8(exporter.finish()?)?;
+    assert_eq!(graphml.matches("<node ").count(), 8);
+    // only the link each url was discovered through is known
+    assert_eq!(graphml.matches("<edge ").count(), 7);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_robots_test() -> Result<()> {
+    let mock = setup_mocks()
+        .await
+        .context("Failed to setup mock server")?
+        .mock(
+            "GET",
+            "/robots.txt",
+            "User-agent: *\nDisallow: /crawl3\nCrawl-delay: 1\nSitemap: /sitemap.xml",
+        )
+        .await
+        .context("Failed to add robots mock")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_concurrent(2)
+        .await?;
+
+    // crawl3 is recorded but never requested so crawl5 and crawl6 are never found.
+    let mut expected = vec![
+        format!("{}/crawl", mock_url),
+        format!("{}/crawl2", mock_url),
+        format!("{}/crawl3", mock_url),
+        format!("{}/crawl4", mock_url),
+        format!("{}/crawl7", mock_url),
+        format!("{}/crawl8", mock_url),
+    ];
+    expected.sort();
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(expected, actual);
+
+    let skipped = &simple_crawler.urls[&format!("{}/crawl3", mock_url)];
+    assert_eq!(skipped.skip_reason(), Some(&SkipReason::RobotsDisallowed));
+    assert!(!skipped.crawled());
+
+    let robots = simple_crawler.robots().context("robots.txt not loaded")?;
+    assert_eq!(robots.sitemaps(), [format!("{}/sitemap.xml", mock_url)]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_robots_size_test() -> Result<()> {
+    // Only the first 500 KiB of robots.txt is read so the rule after the padding is ignored.
+    let robots = format!(
+        "User-agent: *\nDisallow: /crawl3\n#{}\nDisallow: /crawl2\n",
+        "a".repeat(600 * 1024)
+    );
+    let mock = setup_mocks()
+        .await
+        .context("Failed to setup mock server")?
+        .mock("GET", "/robots.txt", &robots)
+        .await
+        .context("Failed to add robots mock")?;
+    let mock_url = mock.mock_server.uri();
+
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    assert!(simple_crawler.urls[&format!("{}/crawl2", mock_url)].crawled());
+    let skipped = &simple_crawler.urls[&format!("{}/crawl3", mock_url)];
+    assert_eq!(skipped.skip_reason(), Some(&SkipReason::RobotsDisallowed));
+
+    Ok(())
+}
 
-    let mock_url = setup_mocks().await
-        .with_context(|| format!("Failed to setup mock server"))?;
+#[tokio::test]
+async fn crawl_robots_per_host_test() -> Result<()> {
+    // Both servers are on 127.0.0.1 so they're in the same host's scope but have their own
+    // robots.txt.
+    let other = SimpleCrawlerMock::new()
+        .await?
+        .mock(
+            "GET",
+            "/robots.txt",
+            "User-agent: *\nDisallow: /private\nCrawl-delay: 0.2",
+        )
+        .await
+        .context("Failed to add other robots mock")?
+        .mock("GET", "/public", "")
+        .await
+        .context("Failed to add public mock")?;
+    let other_url = other.mock_server.uri();
+    let mock = SimpleCrawlerMock::new()
+        .await?
+        .mock(
+            "GET",
+            "/crawl",
+            format!(
+                "<a href=\"{0}/public\">a</a><a href=\"{0}/private\">a</a>\
+                <a href=\"/private\">a</a>",
+                other_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/private", "")
+        .await
+        .context("Failed to add private mock")?;
+    let mock_url = mock.mock_server.uri();
 
     // do crawl
     let simple_crawler = SimpleCrawler::new()
         .url(format!("{}/crawl", mock_url).as_str())?
         .crawl_concurrent(2)
+        .await?;
+
+    // Only the other server's robots.txt disallows its private page.
+    let other_private = &simple_crawler.urls[&format!("{}/private", other_url)];
+    assert_eq!(
+        other_private.skip_reason(),
+        Some(&SkipReason::RobotsDisallowed)
+    );
+    assert!(simple_crawler.urls[&format!("{}/private", mock_url)].crawled());
+    assert!(simple_crawler.urls[&format!("{}/public", other_url)].crawled());
+    let robots = simple_crawler
+        .robots_for(&other_url)
+        .context("robots.txt of the other server not loaded")?;
+    assert_eq!(
+        robots.crawl_delay("simple_crawler"),
+        Some(Duration::from_millis(200))
+    );
+
+    let requests = other
+        .mock_server
+        .received_requests()
+        .await
+        .context("Requests weren't recorded")?;
+    let mut paths: Vec<String> = requests.iter().map(|r| r.url.path().to_owned()).collect();
+    paths.sort();
+    assert_eq!(paths, ["/public", "/robots.txt"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_scope_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .scope(ScopePolicy::same_domain().exclude("/crawl[34]$")?)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_concurrent(2)
+        .await?;
+
+    // Links out of scope aren't recorded so nothing past crawl3 and crawl4 is found.
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(
+        actual,
+        [
+            format!("{}/crawl", mock_url),
+            format!("{}/crawl2", mock_url)
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_relative_links_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    Mock::given(method("GET"))
+        .and(path("/old"))
+        .respond_with(ResponseTemplate::new(301).insert_header("Location", "/docs/index.html"))
+        .mount(&mock.mock_server)
         .await;
-    if simple_crawler.is_ok() {
-        let expected = mock_expected_results(mock_url);
-        let mut actual: Vec<String> = simple_crawler.unwrap().urls.keys().cloned().collect();
-        actual.sort();
-        assert_eq!(expected, actual);
-    }
+    let mock = mock
+        .mock(
+            "GET",
+            "/docs/index.html",
+            "<a href=\"page.html\">page</a><a href=\"../about\">about</a>",
+        )
+        .await
+        .context("Failed to add index mock")?
+        .mock(
+            "GET",
+            "/docs/page.html",
+            "<base href=\"/other/\"><a href=\"./x\">x</a>",
+        )
+        .await
+        .context("Failed to add page mock")?;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/old", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // Links are resolved against where /old redirected to and the base element.
+    let mut expected = vec![
+        format!("{}/old", mock_url),
+        format!("{}/docs/page.html", mock_url),
+        format!("{}/about", mock_url),
+        format!("{}/other/x", mock_url),
+    ];
+    expected.sort();
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(expected, actual);
+    drop(mock);
 
     Ok(())
 }
 
 #[tokio::test]
-async fn crawl_parallel_test() -> Result<()> {
+async fn crawl_canonical_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/crawl",
+            "<a href=\"/a\">a</a><a href=\"./a\">a</a><a href=\"/%62\">b</a>",
+        )
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/a", "")
+        .await
+        .context("Failed to add a mock")?
+        .mock(
+            "GET",
+            "/b",
+            "<link rel=\"canonical\" href=\"/a\"><a href=\"/c\">c</a>",
+        )
+        .await
+        .context("Failed to add b mock")?
+        .mock("GET", "/c", "<a href=\"/b\">b</a>")
+        .await
+        .context("Failed to add c mock")?;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // Different spellings of a url are one page and links to an alias go to its canonical url.
+    let url = |p: &str| format!("{}/{}", mock_url, p);
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(actual, [url("a"), url("b"), url("c"), url("crawl")]);
+    assert_eq!(simple_crawler.aliases().get(&url("b")), Some(&url("a")));
+    assert_eq!(
+        simple_crawler.urls[&url("b")].canonical_url(),
+        Some(url("a").as_str())
+    );
+    assert_eq!(
+        simple_crawler.urls[&url("a")].referrers(),
+        [url("crawl"), url("b"), url("c")]
+    );
+    drop(mock);
+
+    Ok(())
+}
 
-    let mock_url = setup_mocks().await
-        .with_context(|| format!("Failed to setup mock server"))?;
+#[tokio::test]
+async fn crawl_query_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/crawl",
+            "<a href=\"#top\">top</a>\
+            <a href=\"/list?page=2\">2</a>\
+            <a href=\"/list?page=3#results\">3</a>\
+            <a href=\"/list?utm_source=feed&page=2\">2</a>\
+            <a href=\"/list?page=4\">4</a>",
+        )
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/list", "")
+        .await
+        .context("Failed to add list mock")?;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .max_query_variants(2)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // Fragments and tracking parameters are dropped and only two variants of /list are crawled.
+    let url = |p: &str| format!("{}/{}", mock_url, p);
+    let mut actual: Vec<String> = simple_crawler.urls.keys().cloned().collect();
+    actual.sort();
+    assert_eq!(
+        actual,
+        [
+            url("crawl"),
+            url("list?page=2"),
+            url("list?page=3"),
+            url("list?page=4")
+        ]
+    );
+    assert!(simple_crawler.urls[&url("list?page=3")].crawled());
+    assert_eq!(
+        simple_crawler.urls[&url("list?page=4")].skip_reason(),
+        Some(&SkipReason::Limit(Limit::MaxQueryVariants))
+    );
+    assert!(simple_crawler.urls[&url("crawl")].referrers().is_empty());
+    drop(mock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_link_kinds_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/crawl",
+            "<img src=\"/logo.png\"><script src=\"/app.js\"></script>\
+            <iframe src=\"/frame\"></iframe>",
+        )
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/frame", "<a href=\"/logo.png\">logo</a>")
+        .await
+        .context("Failed to add frame mock")?
+        .mock("GET", "/logo.png", "")
+        .await
+        .context("Failed to add logo mock")?;
 
     // do crawl
     let simple_crawler = SimpleCrawler::new()
         .url(format!("{}/crawl", mock_url).as_str())?
-        .crawl_parallel(2)
+        .crawl()
+        .await?;
+
+    // Assets are recorded but only requested once a followed link to them is found.
+    let page = |p: &str| &simple_crawler.urls[&format!("{}/{}", mock_url, p)];
+    assert!(page("frame").crawled());
+    assert_eq!(
+        page("frame").discovered_by().map(|l| l.kind()),
+        Some(LinkKind::Frame)
+    );
+    assert_eq!(page("app.js").skip_reason(), Some(&SkipReason::NotFollowed));
+    assert_eq!(
+        page("app.js").discovered_by().map(|l| l.kind()),
+        Some(LinkKind::Script)
+    );
+    assert!(page("logo.png").crawled());
+    assert_eq!(
+        page("logo.png").discovered_by().map(|l| l.kind()),
+        Some(LinkKind::Anchor)
+    );
+    drop(mock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_nofollow_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/crawl",
+            "<a href=\"/nofollow\" rel=\"nofollow\">a</a>\
+            <a href=\"/ugc\" rel=\"ugc sponsored\">a</a>\
+            <a href=\"/meta\">a</a>",
+        )
+        .await
+        .context("Failed to add crawl mock")?
+        .mock(
+            "GET",
+            "/meta",
+            "<meta name=\"robots\" content=\"noindex, nofollow\"><a href=\"/hidden\">a</a>",
+        )
+        .await
+        .context("Failed to add meta mock")?;
+    Mock::given(method("GET"))
+        .and(path("/tagged"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("<a href=\"/hidden2\">a</a>")
+                .insert_header("X-Robots-Tag", "nofollow"),
+        )
+        .mount(&mock.mock_server)
         .await;
-    if simple_crawler.is_ok() {
-        let expected = mock_expected_results(mock_url);
-        let mut actual: Vec<String> = simple_crawler.unwrap().urls.keys().cloned().collect();
-        actual.sort();
-        assert_eq!(expected, actual);
+
+    // nofollow links and robots directives are obeyed by default
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .url(format!("{}/tagged", mock_url).as_str())?
+        .crawl()
+        .await?;
+    let page = |p: &str| &simple_crawler.urls[&format!("{}/{}", mock_url, p)];
+    for p in &["nofollow", "ugc", "hidden", "hidden2"] {
+        assert_eq!(page(p).skip_reason(), Some(&SkipReason::NoFollow));
     }
+    assert!(page("nofollow")
+        .discovered_by()
+        .is_some_and(|l| l.nofollow()));
+    assert!(page("meta").robots_directives().noindex());
+    assert!(page("meta").robots_directives().nofollow());
+    assert!(!page("tagged").robots_directives().noindex());
+    assert!(page("tagged").robots_directives().nofollow());
+
+    // they're recorded but ignored when switched off
+    let simple_crawler = SimpleCrawler::builder()
+        .obey_robots_directives(false)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+    let page = |p: &str| &simple_crawler.urls[&format!("{}/{}", mock_url, p)];
+    assert_eq!(page("nofollow").status(), Some(404));
+    assert_eq!(page("hidden").status(), Some(404));
+    assert!(page("meta").robots_directives().nofollow());
+    drop(mock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_sitemaps_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mut pages = GzEncoder::new(vec![], Compression::default());
+    pages.write_all(
+        format!(
+            "<urlset><url><loc>{}/listed</loc><lastmod>2021-03-01</lastmod>\
+            <changefreq>daily</changefreq><priority>0.5</priority></url></urlset>",
+            mock_url
+        )
+        .as_bytes(),
+    )?;
+    Mock::given(method("GET"))
+        .and(path("/pages.xml.gz"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(pages.finish()?))
+        .mount(&mock.mock_server)
+        .await;
+    let mock = mock
+        .mock(
+            "GET",
+            "/robots.txt",
+            format!("Sitemap: {}/index.xml", mock_url).as_ref(),
+        )
+        .await
+        .context("Failed to add robots mock")?
+        .mock(
+            "GET",
+            "/index.xml",
+            format!(
+                "<sitemapindex><sitemap><loc>{}/pages.xml.gz</loc></sitemap></sitemapindex>",
+                mock_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add index mock")?
+        .mock(
+            "GET",
+            "/sitemap.xml",
+            format!(
+                "<urlset><url><loc>{0}/orphan</loc></url>\
+                <url><loc>https://other.com/a</loc></url></urlset>",
+                mock_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add sitemap mock")?
+        .mock(
+            "GET",
+            "/crawl",
+            "<a href=\"/listed\">a</a><a href=\"/linked\">a</a>",
+        )
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/listed", "")
+        .await
+        .context("Failed to add listed mock")?
+        .mock("GET", "/linked", "")
+        .await
+        .context("Failed to add linked mock")?
+        .mock("GET", "/orphan", "")
+        .await
+        .context("Failed to add orphan mock")?;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .sitemaps(true)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    let url = |p: &str| format!("{}/{}", mock_url, p);
+    assert_eq!(
+        simple_crawler.sitemaps(),
+        Some(&[url("index.xml"), url("sitemap.xml"), url("pages.xml.gz")][..])
+    );
+    assert!(simple_crawler.urls[&url("orphan")].crawled());
+    assert!(!simple_crawler.urls.contains_key("https://other.com/a"));
+    let entry = simple_crawler.urls[&url("listed")]
+        .sitemap()
+        .context("listed page has no sitemap entry")?;
+    assert_eq!(entry.sitemap(), url("pages.xml.gz"));
+    assert_eq!(entry.lastmod(), Some("2021-03-01"));
+    assert_eq!(entry.changefreq(), Some(ChangeFreq::Daily));
+    assert_eq!(entry.priority(), Some(0.5));
+
+    let report = simple_crawler.sitemap_report()?;
+    assert_eq!(report.only_in_sitemap(), [url("orphan")]);
+    assert_eq!(report.missing_from_sitemap(), [url("linked")]);
+    drop(mock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_sitemaps_robots_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new().await?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/robots.txt",
+            format!(
+                "User-agent: *\nDisallow: /private/\nSitemap: {}/private/sitemap.xml",
+                mock_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add robots mock")?
+        .mock(
+            "GET",
+            "/sitemap.xml",
+            format!(
+                "<sitemapindex><sitemap><loc>{0}/private/nested.xml</loc></sitemap>\
+                <sitemap><loc>{0}/pages.xml</loc></sitemap></sitemapindex>",
+                mock_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add index mock")?
+        .mock(
+            "GET",
+            "/pages.xml",
+            format!("<urlset><url><loc>{}/listed</loc></url></urlset>", mock_url).as_ref(),
+        )
+        .await
+        .context("Failed to add pages mock")?
+        .mock("GET", "/crawl", "")
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/listed", "")
+        .await
+        .context("Failed to add listed mock")?;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .sitemaps(true)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // Sitemaps robots.txt disallows are never requested whether they're listed in robots.txt
+    // or in an index.
+    let url = |p: &str| format!("{}/{}", mock_url, p);
+    assert_eq!(
+        simple_crawler.sitemaps(),
+        Some(&[url("sitemap.xml"), url("pages.xml")][..])
+    );
+    assert!(simple_crawler.urls[&url("listed")].crawled());
+    let requests = mock
+        .mock_server
+        .received_requests()
+        .await
+        .context("Requests weren't recorded")?;
+    assert!(requests
+        .iter()
+        .all(|r| !r.url.path().starts_with("/private/")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_sitemaps_redirect_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new().await?;
+    let mock_url = mock.mock_server.uri();
+    let redirects = [
+        ("/sitemap.xml", "/moved.xml"),
+        ("/hop.xml", "/private/sitemap.xml"),
+        ("/away.xml", "/excluded/away.xml"),
+    ];
+    for (from, to) in redirects.iter() {
+        Mock::given(method("GET"))
+            .and(path(*from))
+            .respond_with(ResponseTemplate::new(301).insert_header("Location", *to))
+            .mount(&mock.mock_server)
+            .await;
+    }
+    let mock = mock
+        .mock("GET", "/robots.txt", "User-agent: *\nDisallow: /private/")
+        .await
+        .context("Failed to add robots mock")?
+        .mock(
+            "GET",
+            "/moved.xml",
+            format!(
+                "<sitemapindex><sitemap><loc>{0}/pages.xml</loc></sitemap>\
+                <sitemap><loc>{0}/excluded/nested.xml</loc></sitemap>\
+                <sitemap><loc>{0}/hop.xml</loc></sitemap>\
+                <sitemap><loc>{0}/away.xml</loc></sitemap></sitemapindex>",
+                mock_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add index mock")?
+        .mock(
+            "GET",
+            "/pages.xml",
+            format!("<urlset><url><loc>{}/listed</loc></url></urlset>", mock_url).as_ref(),
+        )
+        .await
+        .context("Failed to add pages mock")?
+        .mock("GET", "/crawl", "")
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/listed", "")
+        .await
+        .context("Failed to add listed mock")?;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .sitemaps(true)
+        .scope(ScopePolicy::same_host().exclude("/excluded/")?)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // Sitemap redirects are followed in scope. Nested sitemaps and redirects outside the scope or
+    // disallowed by robots.txt are never requested.
+    let url = |p: &str| format!("{}/{}", mock_url, p);
+    assert_eq!(
+        simple_crawler.sitemaps(),
+        Some(&[url("sitemap.xml"), url("pages.xml")][..])
+    );
+    assert!(simple_crawler.urls[&url("listed")].crawled());
+    let requests = mock
+        .mock_server
+        .received_requests()
+        .await
+        .context("Requests weren't recorded")?;
+    assert!(requests.iter().any(|r| r.url.path() == "/moved.xml"));
+    assert!(
+        requests
+            .iter()
+            .all(|r| !r.url.path().starts_with("/private/")
+                && !r.url.path().starts_with("/excluded/"))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_resume_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let dir = std::env::temp_dir().join(format!("crawl_resume_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let checkpoint = dir.join("crawl.db");
+
+    // the first crawl stops part way through
+    let simple_crawler = SimpleCrawler::builder()
+        .max_pages(3)
+        .checkpoint(&checkpoint)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+    assert_eq!(simple_crawler.limit_reached(), Some(Limit::MaxPages));
+
+    // resuming carries on without requesting fetched pages again
+    let simple_crawler = SimpleCrawler::builder()
+        .build()
+        .resume(&checkpoint)?
+        .crawl()
+        .await?;
+    assert_eq!(simple_crawler.limit_reached(), None);
+    assert!(simple_crawler.urls.values().all(|page| page.crawled()));
+    assert_eq!(
+        simple_crawler.urls[&format!("{}/crawl3", mock_url)].referrers(),
+        [
+            format!("{}/crawl", mock_url),
+            format!("{}/crawl2", mock_url)
+        ]
+    );
+
+    let requests = mock
+        .mock_server
+        .received_requests()
+        .await
+        .context("Requests weren't recorded")?;
+    let mut paths: Vec<String> = requests
+        .iter()
+        .map(|r| r.url.path().to_owned())
+        .filter(|p| p != "/robots.txt")
+        .collect();
+    paths.sort();
+    let expected = vec![
+        "/crawl", "/crawl2", "/crawl3", "/crawl4", "/crawl5", "/crawl6", "/crawl7", "/crawl8",
+    ];
+    assert_eq!(expected, paths);
+
+    // the checkpoint holds the finished crawl
+    let resumed = SimpleCrawler::new().resume(&checkpoint)?;
+    assert_eq!(resumed.urls, simple_crawler.urls);
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_resume_redirect_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?
+        .mock("GET", "/crawl", "<a href=\"/a\">a</a><a href=\"/c\">c</a>")
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/c", "<a href=\"/b\">b</a>")
+        .await
+        .context("Failed to add c mock")?;
+    let mock_url = mock.mock_server.uri();
+    Mock::given(method("GET"))
+        .and(path("/a"))
+        .respond_with(ResponseTemplate::new(301).insert_header("Location", "/b"))
+        .mount(&mock.mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/b"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock.mock_server)
+        .await;
+    let dir =
+        std::env::temp_dir().join(format!("crawl_resume_redirect_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let checkpoint = dir.join("crawl.db");
+
+    // the first crawl follows /a to /b then stops before /c
+    let simple_crawler = SimpleCrawler::builder()
+        .max_pages(2)
+        .checkpoint(&checkpoint)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+    assert_eq!(simple_crawler.limit_reached(), Some(Limit::MaxPages));
+    assert!(!simple_crawler.urls.contains_key(&format!("{}/b", mock_url)));
+
+    // the link from /c to /b is recorded against /a rather than fetching /b again
+    let simple_crawler = SimpleCrawler::new().resume(&checkpoint)?.crawl().await?;
+    assert_eq!(
+        simple_crawler.aliases()[&format!("{}/b", mock_url)],
+        format!("{}/a", mock_url)
+    );
+    assert!(simple_crawler.urls[&format!("{}/c", mock_url)].crawled());
+    assert!(!simple_crawler.urls.contains_key(&format!("{}/b", mock_url)));
+    assert_eq!(
+        simple_crawler.urls[&format!("{}/a", mock_url)].referrers(),
+        [format!("{}/crawl", mock_url), format!("{}/c", mock_url)]
+    );
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_sqlite_store_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let dir = std::env::temp_dir().join(format!("crawl_sqlite_store_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    // every page is kept on disk rather than in memory
+    let store = SqliteStore::open(dir.join("store.db"))?;
+    let simple_crawler = SimpleCrawler::builder()
+        .max_depth(5)
+        .build_with_store(store)
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(2)
+        .await?;
+    let pages = simple_crawler
+        .urls
+        .iter()
+        .collect::<Result<Vec<_>, CrawlError>>()?;
+    let mut actual: Vec<String> = pages.iter().map(|(url, _)| url.to_owned()).collect();
+    actual.sort();
+    assert_eq!(mock_expected_results(mock_url.to_owned()), actual);
+    assert!(pages.iter().all(|(_, page)| page.crawled()));
+    let page = simple_crawler
+        .urls
+        .get(&format!("{}/crawl3", mock_url))?
+        .context("crawl3 wasn't stored")?;
+    assert_eq!(page.referrers().len(), 2);
+    assert_eq!(page.body(), "");
+
+    // a streamed crawl can keep its pages on disk too
+    let events: Vec<CrawlEvent> = SimpleCrawler::builder()
+        .build_with_store(SqliteStore::open(dir.join("stream.db"))?)
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_stream(2)?
+        .collect()
+        .await;
+    let fetched = events
+        .iter()
+        .filter(|event| matches!(event, CrawlEvent::PageFetched { .. }))
+        .count();
+    assert_eq!(fetched, 8);
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_metadata_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    Mock::given(method("GET"))
+        .and(path("/crawl"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw("<p>aaa</p>", "text/html")
+                .insert_header("etag", "\"abc\"")
+                .insert_header("x-not-recorded", "1"),
+        )
+        .mount(&mock.mock_server)
+        .await;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl", mock_url)];
+    assert!(page.crawled());
+    assert_eq!(page.status(), Some(200));
+    assert_eq!(
+        page.final_url(),
+        Some(format!("{}/crawl", mock_url).as_str())
+    );
+    assert_eq!(page.content_type(), Some("text/html"));
+    assert_eq!(page.content_length(), Some(10));
+    assert_eq!(page.header("ETag"), Some("\"abc\""));
+    assert_eq!(page.header("x-not-recorded"), None);
+    assert!(page.latency().is_some());
+    assert!(page.fetched_at().is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_page_metadata_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?
+        .mock(
+            "GET",
+            "/crawl",
+            "<head><title>Home</title>\
+            <meta name=\"description\" content=\"The home page\">\
+            <link rel=\"canonical\" href=\"/crawl\">\
+            <link rel=\"alternate\" hreflang=\"de\" href=\"/de/crawl\">\
+            <meta property=\"og:title\" content=\"Home\">\
+            <script type=\"application/ld+json\">{\"@type\": \"WebSite\"}</script></head>\
+            <body><h1>Welcome</h1><h2>News</h2></body>",
+        )
+        .await
+        .context("Failed to add mock")?;
+    let mock_url = mock.mock_server.uri();
+
+    let simple_crawler = SimpleCrawler::builder()
+        .extract_metadata(true)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl", mock_url)];
+    let metadata = page.metadata().context("metadata not extracted")?;
+    assert_eq!(metadata.title(), Some("Home"));
+    assert_eq!(metadata.description(), Some("The home page"));
+    assert_eq!(metadata.headings_at(1).collect::<Vec<_>>(), ["Welcome"]);
+    assert_eq!(
+        metadata.canonical(),
+        Some(format!("{}/crawl", mock_url).as_str())
+    );
+    assert_eq!(
+        metadata.hreflang(),
+        [("de".to_owned(), format!("{}/de/crawl", mock_url))]
+    );
+    assert_eq!(metadata.open_graph()[0].1, "Home");
+    assert_eq!(metadata.json_ld(), ["{\"@type\": \"WebSite\"}"]);
+    // The body is still dropped once the metadata has been read.
+    assert_eq!(page.body(), "");
+
+    // Metadata is only read when it's asked for.
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+    assert_eq!(
+        simple_crawler.urls[&format!("{}/crawl", mock_url)].metadata(),
+        None
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_body_retention_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let dir = std::env::temp_dir().join(format!("crawl_bodies_{}", std::process::id()));
+
+    let simple_crawler = SimpleCrawler::builder()
+        .body_retention(BodyRetention::Disk(dir.to_owned()))
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_concurrent(2)
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl2", mock_url)];
+    let stored = page.stored_body().context("body not kept")?;
+    assert!(stored.path().context("body not on disk")?.starts_with(&dir));
+    assert!(stored.read()?.contains("/crawl4"));
+    // Every unmocked page has the same empty body so it's only written once.
+    let crawl5 = &simple_crawler.urls[&format!("{}/crawl5", mock_url)];
+    let crawl6 = &simple_crawler.urls[&format!("{}/crawl6", mock_url)];
+    assert_eq!(crawl5.stored_body(), crawl6.stored_body());
+    std::fs::remove_dir_all(&dir)?;
+
+    // Bodies over the size cap aren't kept in memory.
+    let simple_crawler = SimpleCrawler::builder()
+        .body_retention(BodyRetention::Memory(10))
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+    assert_eq!(
+        simple_crawler.urls[&format!("{}/crawl", mock_url)].stored_body(),
+        None
+    );
+    assert_eq!(
+        simple_crawler.urls[&format!("{}/crawl5", mock_url)].stored_body(),
+        Some(&StoredBody::Memory("".to_owned()))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_archive_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let dir = std::env::temp_dir().join(format!("crawl_warcs_{}", std::process::id()));
+
+    let simple_crawler = SimpleCrawler::builder()
+        .archive(Archive::new(&dir).prefix("test").max_file_size(1000))
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(2)
+        .await?;
+    let fetched = simple_crawler
+        .urls
+        .values()
+        .filter(|page| page.attempts() > 0)
+        .count();
+
+    let mut files = std::fs::read_dir(&dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    files.sort();
+    // Small files make the archive rotate.
+    assert!(files.len() > 1);
+    let mut types = vec![];
+    for file in files.iter() {
+        let name = file.file_name().context("no file name")?.to_string_lossy();
+        assert!(name.starts_with("test-") && name.ends_with(".warc.gz"));
+        let mut warc = vec![];
+        MultiGzDecoder::new(std::fs::File::open(file)?).read_to_end(&mut warc)?;
+        // Walk the records using their Content-Length to check every one is well formed.
+        let mut rest = warc.as_slice();
+        while !rest.is_empty() {
+            let end = rest
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .context("record headers not ended")?;
+            let headers = String::from_utf8_lossy(&rest[..end]).into_owned();
+            assert!(headers.starts_with("WARC/1.1\r\n"));
+            let header = |name: &str| {
+                headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|value| value.to_owned())
+            };
+            let length: usize = header("Content-Length: ")
+                .context("no Content-Length")?
+                .parse()?;
+            assert!(header("WARC-Record-ID: <urn:uuid:").is_some());
+            assert!(header("WARC-Block-Digest: sha256:").is_some());
+            types.push(header("WARC-Type: ").context("no WARC-Type")?);
+            rest = &rest[end + 4 + length..];
+            assert!(rest.starts_with(b"\r\n\r\n"));
+            rest = &rest[4..];
+        }
+        assert_eq!(types[types.len() - 1], "metadata");
+    }
+    let count = |kind: &str| types.iter().filter(|t| t.as_str() == kind).count();
+    assert_eq!(count("warcinfo"), files.len());
+    assert_eq!(count("request"), fetched);
+    assert_eq!(count("response"), fetched);
+    assert_eq!(count("metadata"), fetched);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_failure_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/crawl",
+            format!("<a href=\"{}/fail\">aaa</a>", mock_url).as_ref(),
+        )
+        .await
+        .context("Failed to add mock")?;
+    Mock::given(method("GET"))
+        .and(path("/fail"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(2)
+        .mount(&mock.mock_server)
+        .await;
+    let retry = RetryPolicy::new()
+        .max_attempts(2)
+        .backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+    // The failed page ends the crawl as failed rather than being requested forever.
+    let simple_crawler = SimpleCrawler::builder()
+        .retry_policy(retry)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_concurrent(2)
+        .await?;
+
+    let failed = &simple_crawler.urls[&format!("{}/fail", mock_url)];
+    assert!(!failed.crawled());
+    assert!(matches!(
+        failed.error(),
+        Some(CrawlError::Status { status: 503, .. })
+    ));
+    assert_eq!(failed.attempts(), 2);
+    assert_eq!(failed.status(), Some(503));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_redirect_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let links = ["old", "new", "moved", "loop1", "chain1", "away"]
+        .iter()
+        .map(|p| format!("<a href=\"{}/{}\">a</a>", mock_url, p))
+        .collect::<String>();
+    let mock = mock
+        .mock("GET", "/crawl", &links)
+        .await
+        .context("Failed to add mock")?
+        .mock("GET", "/chain3", "")
+        .await
+        .context("Failed to add mock")?;
+    let redirects = [
+        ("/old", "/new"),
+        ("/moved", "/moved-to"),
+        ("/loop1", "/loop2"),
+        ("/loop2", "/loop1"),
+        ("/chain1", "/chain2"),
+        ("/chain2", "/chain3"),
+        ("/away", "https://other.invalid/"),
+    ];
+    for (from, to) in redirects.iter() {
+        Mock::given(method("GET"))
+            .and(path(*from))
+            .respond_with(ResponseTemplate::new(301).insert_header("Location", *to))
+            .mount(&mock.mock_server)
+            .await;
+    }
+    // Neither the target of a redirect nor a url already queued is fetched twice.
+    Mock::given(method("GET"))
+        .and(path("/new"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock.mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/moved-to"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<a href=\"/moved-to\">a</a>"))
+        .expect(1)
+        .mount(&mock.mock_server)
+        .await;
+
+    let simple_crawler = SimpleCrawler::builder()
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+    let page = |p: &str| &simple_crawler.urls[&format!("{}{}", mock_url, p)];
+
+    let moved = page("/moved");
+    assert!(moved.crawled());
+    assert_eq!(moved.status(), Some(200));
+    assert_eq!(
+        moved.final_url(),
+        Some(format!("{}/moved-to", mock_url).as_str())
+    );
+    assert_eq!(moved.redirects().len(), 1);
+    assert_eq!(moved.redirects()[0].status(), 301);
+    assert_eq!(
+        moved.redirects()[0].location(),
+        format!("{}/moved-to", mock_url)
+    );
+    assert_eq!(
+        simple_crawler.aliases()[&format!("{}/moved-to", mock_url)],
+        format!("{}/moved", mock_url)
+    );
+    assert!(!simple_crawler
+        .urls
+        .contains_key(&format!("{}/moved-to", mock_url)));
+    // A redirect to a url which is already queued isn't followed and the url is fetched itself.
+    let old = page("/old");
+    assert!(old.crawled());
+    assert_eq!(old.status(), Some(301));
+    assert_eq!(old.redirects()[0].location(), format!("{}/new", mock_url));
+    assert!(page("/new").crawled());
+    assert!(!simple_crawler
+        .aliases()
+        .contains_key(&format!("{}/new", mock_url)));
+
+    assert!(matches!(
+        page("/loop1").error(),
+        Some(CrawlError::RedirectLoop { .. })
+    ));
+    assert_eq!(page("/loop1").redirects().len(), 2);
+    assert_eq!(page("/chain1").redirects().len(), 2);
+    assert_eq!(page("/chain1").status(), Some(200));
+    // Redirects out of scope aren't followed so the redirect is the page's response.
+    let away = page("/away");
+    assert!(away.crawled());
+    assert_eq!(away.status(), Some(301));
+    assert_eq!(away.redirects()[0].location(), "https://other.invalid/");
+
+    let report = simple_crawler.redirect_report()?;
+    assert_eq!(report.loops(), [format!("{}/loop1", mock_url)]);
+    assert_eq!(report.long_chains(), [format!("{}/chain1", mock_url)]);
+
+    let simple_crawler = SimpleCrawler::builder()
+        .retry_policy(RetryPolicy::none())
+        .redirect_policy(RedirectPolicy::new().max_hops(1))
+        .build()
+        .url(format!("{}/chain1", mock_url).as_str())?
+        .crawl()
+        .await?;
+    let chain = &simple_crawler.urls[&format!("{}/chain1", mock_url)];
+    assert!(matches!(
+        chain.error(),
+        Some(CrawlError::TooManyRedirects { max_hops: 1, .. })
+    ));
+    assert_eq!(chain.redirects().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_redirect_robots_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?
+        .mock("GET", "/robots.txt", "User-agent: *\nDisallow: /private")
+        .await
+        .context("Failed to add robots mock")?
+        .mock("GET", "/crawl", "<a href=\"/old\">a</a>")
+        .await
+        .context("Failed to add crawl mock")?
+        .mock("GET", "/private/page", "")
+        .await
+        .context("Failed to add private mock")?;
+    let mock_url = mock.mock_server.uri();
+    Mock::given(method("GET"))
+        .and(path("/old"))
+        .respond_with(ResponseTemplate::new(301).insert_header("Location", "/private/page"))
+        .mount(&mock.mock_server)
+        .await;
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::new()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // The redirect into a disallowed path stops at the redirect and its target is skipped.
+    let old = &simple_crawler.urls[&format!("{}/old", mock_url)];
+    assert!(old.crawled());
+    assert_eq!(old.status(), Some(301));
+    let private = &simple_crawler.urls[&format!("{}/private/page", mock_url)];
+    assert_eq!(private.skip_reason(), Some(&SkipReason::RobotsDisallowed));
+    assert_eq!(private.depth(), old.depth());
+
+    let requests = mock
+        .mock_server
+        .received_requests()
+        .await
+        .context("Requests weren't recorded")?;
+    let mut paths: Vec<String> = requests.iter().map(|r| r.url.path().to_owned()).collect();
+    paths.sort();
+    assert_eq!(paths, ["/crawl", "/old", "/robots.txt"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_serde_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    let mock = mock
+        .mock(
+            "GET",
+            "/crawl",
+            format!(
+                "<a href=\"{0}/fail\">a</a><a href=\"{0}/skip\">b</a>\
+                <a href=\"http://127.0.0.1:1/down\">c</a>",
+                mock_url
+            )
+            .as_ref(),
+        )
+        .await
+        .context("Failed to add mock")?;
+    Mock::given(method("GET"))
+        .and(path("/fail"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&mock.mock_server)
+        .await;
+
+    let simple_crawler = SimpleCrawler::builder()
+        .retry_policy(RetryPolicy::none())
+        .scope(ScopePolicy::same_host().exclude("/skip$")?)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    // A whole crawl, failures and compiled scope patterns included, survives a round trip.
+    let json = serde_json::to_string(&simple_crawler)?;
+    let restored: SimpleCrawler = serde_json::from_str(&json)?;
+    assert_eq!(restored, simple_crawler);
+    // Failures come back as the kind of error they were.
+    let down = restored.urls["http://127.0.0.1:1/down"].error();
+    assert!(matches!(down, Some(CrawlError::Connect { .. })));
+
+    let value = serde_json::to_value(&simple_crawler)?;
+    let pages = &value["urls"];
+    assert_eq!(pages[format!("{}/crawl", mock_url)]["state"], "fetched");
+    assert!(pages.get(format!("{}/skip", mock_url)).is_none());
+    let error = &pages[format!("{}/fail", mock_url)]["state"]["failed"];
+    assert_eq!(error["kind"], "status");
+    assert_eq!(error["status"], 503);
+    assert_eq!(value["config"]["scope"]["exclude"][0], "/skip$");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_retry_after_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    Mock::given(method("GET"))
+        .and(path("/crawl"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .mount(&mock.mock_server)
+        .await;
+    let mock = mock
+        .mock("GET", "/crawl", "")
+        .await
+        .context("Failed to add mock")?;
+    let retry = RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+    // The retry waits for the host's Retry-After rather than the retry policy's backoff.
+    let started = std::time::Instant::now();
+    let simple_crawler = SimpleCrawler::builder()
+        .retry_policy(retry)
+        .politeness(Politeness::new().max_in_flight_per_host(1))
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(4)
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl", mock_url)];
+    assert!(page.crawled());
+    assert_eq!(page.attempts(), 2);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    drop(mock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_timeout_retry_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    Mock::given(method("GET"))
+        .and(path("/crawl"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(600)))
+        .up_to_n_times(1)
+        .mount(&mock.mock_server)
+        .await;
+    let mock = mock
+        .mock("GET", "/crawl", "")
+        .await
+        .context("Failed to add mock")?;
+    let retry = RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+    // The first response is too slow so the request times out and is retried.
+    let simple_crawler = SimpleCrawler::builder()
+        .request_timeout(Duration::from_millis(200))
+        .retry_policy(retry)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl", mock_url)];
+    assert!(page.crawled());
+    assert_eq!(page.error(), None);
+    assert_eq!(page.attempts(), 2);
+    drop(mock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_connect_failure_test() -> Result<()> {
+    // Nothing listens on port 1 so the crawl returns the seed as failed instead of an error.
+    let simple_crawler = SimpleCrawler::builder()
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .url("http://127.0.0.1:1/crawl")?
+        .crawl()
+        .await?;
+
+    let page = &simple_crawler.urls["http://127.0.0.1:1/crawl"];
+    assert!(matches!(page.error(), Some(CrawlError::Connect { .. })));
+    assert_eq!(page.attempts(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_timeout_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?;
+    let mock_url = mock.mock_server.uri();
+    // The server takes the request but never answers.
+    Mock::given(method("GET"))
+        .and(path("/stall"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(600)))
+        .mount(&mock.mock_server)
+        .await;
+    let stall = format!("{}/stall", mock_url);
+
+    let started = std::time::Instant::now();
+    let simple_crawler = SimpleCrawler::builder()
+        .request_timeout(Duration::from_millis(200))
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .url(&stall)?
+        .crawl()
+        .await?;
+    let page = &simple_crawler.urls[&stall];
+    assert!(matches!(page.error(), Some(CrawlError::Timeout { .. })));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // A request still running when the crawl runs out of time is stopped.
+    let started = std::time::Instant::now();
+    let simple_crawler = SimpleCrawler::builder()
+        .max_duration(Duration::from_millis(300))
+        .build()
+        .url(&stall)?
+        .crawl_concurrent(2)
+        .await?;
+    let page = &simple_crawler.urls[&stall];
+    assert!(matches!(page.error(), Some(CrawlError::Timeout { .. })));
+    assert_eq!(page.attempts(), 1);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_base_url_test() {
+    let simple_crawler = SimpleCrawler::new().url("not a url");
+    assert!(matches!(
+        simple_crawler,
+        Err(CrawlError::InvalidBaseUrl { .. })
+    ));
+}
+
+#[tokio::test]
+async fn crawl_max_pages_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .max_pages(3)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_parallel(2)
+        .await?;
+
+    assert_eq!(simple_crawler.limit_reached(), Some(Limit::MaxPages));
+    let requested = simple_crawler
+        .urls
+        .values()
+        .filter(|page| page.attempts() > 0)
+        .count();
+    assert_eq!(requested, 3);
+    assert!(simple_crawler
+        .urls
+        .values()
+        .filter(|page| page.attempts() == 0)
+        .all(|page| page.skip_reason() == Some(&SkipReason::Limit(Limit::MaxPages))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_max_depth_test() -> Result<()> {
+    let mock = setup_mocks().await.context("Failed to setup mock server")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .max_depth(1)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl_concurrent(2)
+        .await?;
+
+    // Pages two links away are recorded but not requested so crawl7 and crawl8 are never found.
+    let page = |p: &str| &simple_crawler.urls[&format!("{}/{}", mock_url, p)];
+    assert_eq!(simple_crawler.urls.len(), 6);
+    assert_eq!(page("crawl").depth(), 0);
+    assert!(page("crawl3").crawled());
+    assert_eq!(page("crawl3").depth(), 1);
+    assert_eq!(
+        page("crawl3").referrers(),
+        [
+            format!("{}/crawl", mock_url),
+            format!("{}/crawl2", mock_url)
+        ]
+    );
+    let link = page("crawl3")
+        .discovered_by()
+        .context("crawl3 has no link")?;
+    assert_eq!(link.source(), format!("{}/crawl", mock_url));
+    assert_eq!(link.text(), "aaa");
+    assert!(page("crawl").discovered_by().is_none());
+    assert_eq!(page("crawl4").depth(), 2);
+    assert_eq!(
+        page("crawl4").skip_reason(),
+        Some(&SkipReason::Limit(Limit::MaxDepth))
+    );
+    assert_eq!(simple_crawler.limit_reached(), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn crawl_max_body_size_test() -> Result<()> {
+    let mock = SimpleCrawlerMock::new()
+        .await
+        .context("Failed to start mock server")?
+        .mock("GET", "/crawl", "<p>this body is too large</p>")
+        .await
+        .context("Failed to add mock")?;
+    let mock_url = mock.mock_server.uri();
+
+    // do crawl
+    let simple_crawler = SimpleCrawler::builder()
+        .max_body_size(10)
+        .build()
+        .url(format!("{}/crawl", mock_url).as_str())?
+        .crawl()
+        .await?;
+
+    let page = &simple_crawler.urls[&format!("{}/crawl", mock_url)];
+    assert!(matches!(
+        page.error(),
+        Some(CrawlError::BodyTooLarge {
+            max_body_size: 10,
+            ..
+        })
+    ));
 
     Ok(())
 }